@@ -0,0 +1,56 @@
+//! Compares the two ways the solver can go back to a choice point: restoring
+//! a `SavedGame` taken there, as `play_game` does, or taking moves back one
+//! at a time from an undo log, as `Game::play_with_undo_log` does. The undo
+//! log is only built with the `undo-log` feature, so run with
+//! `cargo bench --features undo-log --bench backtrack`, adding `-- undo` (or
+//! `snapshot`) for just one.
+use sophies_game::{play_game_with_budget, Game, Result};
+use std::env;
+use std::time::{Duration, Instant};
+
+//a classic deal with a few hundred choice points and both wins and losses
+//among its lines, so every search walks the same mid-sized tree.
+const SEED: u64 = 2;
+const BUDGET: usize = 100_000;
+const RUNS: u32 = 5;
+
+fn time<F: FnMut() -> Result>(name: &str, mut run: F) -> (Duration, Result) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = Some(run());
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "{:<9} {:>10.3}ms  {:?}",
+        name,
+        best.as_secs_f64() * 1000.0,
+        result.unwrap()
+    );
+    (best, result.unwrap())
+}
+
+fn main() {
+    //cargo passes --bench; anything else picks the approaches to run.
+    let only: Vec<String> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect();
+    let wanted = |name: &str| only.is_empty() || only.iter().any(|o| o == name);
+    println!("seed {}, best of {} runs", SEED, RUNS);
+    let snapshot = wanted("snapshot").then(|| {
+        time("snapshot", || {
+            play_game_with_budget(Game::from_seed(SEED), BUDGET).result
+        })
+    });
+    let undo =
+        wanted("undo").then(|| time("undo", || Game::from_seed(SEED).play_with_undo_log(BUDGET)));
+    if let (Some((s, rs)), Some((u, ru))) = (snapshot, undo) {
+        assert_eq!(rs, ru, "both searches should agree on the outcome");
+        println!(
+            "undo log is {:.2}x the speed of snapshots",
+            s.as_secs_f64() / u.as_secs_f64()
+        );
+    }
+}
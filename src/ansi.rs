@@ -0,0 +1,7 @@
+//! Just enough ANSI escape codes to colour the board on a terminal.
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+pub fn red(text: &str) -> String {
+    format!("{}{}{}", RED, text, RESET)
+}
@@ -0,0 +1,91 @@
+use crate::{MoveError, ReplayError, SnapshotError};
+use std::fmt::{self, Display};
+use std::io;
+
+/// Every way a public fallible function in this crate can fail.
+#[derive(Debug)]
+pub enum SophieError {
+    /// Text that should have named something, like a generator, didn't.
+    Parse(String),
+    Replay(ReplayError),
+    IllegalMove(MoveError),
+    BadSnapshot(SnapshotError),
+    Io(io::Error),
+}
+impl Display for SophieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SophieError::Parse(message) => write!(f, "couldn't parse: {}", message),
+            SophieError::Replay(e) => write!(f, "bad replay: {}", e),
+            SophieError::IllegalMove(e) => write!(f, "illegal move: {}", e),
+            SophieError::BadSnapshot(e) => write!(f, "bad snapshot: {}", e),
+            SophieError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for SophieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SophieError::Parse(_) => None,
+            SophieError::Replay(e) => Some(e),
+            SophieError::IllegalMove(e) => Some(e),
+            SophieError::BadSnapshot(e) => Some(e),
+            SophieError::Io(e) => Some(e),
+        }
+    }
+}
+impl From<ReplayError> for SophieError {
+    fn from(e: ReplayError) -> Self {
+        SophieError::Replay(e)
+    }
+}
+impl From<MoveError> for SophieError {
+    fn from(e: MoveError) -> Self {
+        SophieError::IllegalMove(e)
+    }
+}
+impl From<SnapshotError> for SophieError {
+    fn from(e: SnapshotError) -> Self {
+        SophieError::BadSnapshot(e)
+    }
+}
+impl From<serde_json::Error> for SophieError {
+    fn from(e: serde_json::Error) -> Self {
+        SophieError::BadSnapshot(SnapshotError::Malformed(e))
+    }
+}
+impl From<io::Error> for SophieError {
+    fn from(e: io::Error) -> Self {
+        SophieError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Game, Move, RngKind, SavedGame};
+
+    #[test]
+    fn each_failure_has_its_own_variant() {
+        assert!(matches!(
+            "Mersenne".parse::<RngKind>(),
+            Err(SophieError::Parse(_))
+        ));
+        assert!(matches!(
+            Game::from_replay("deal\n"),
+            Err(SophieError::Replay(_))
+        ));
+        assert!(matches!(
+            Game::from_seed(1).apply(Move::Match((0, 1))),
+            Err(SophieError::IllegalMove(MoveError::NotAMatch((0, 1))))
+        ));
+        assert!(matches!(
+            SavedGame::from_json("{}"),
+            Err(SophieError::BadSnapshot(_))
+        ));
+        assert!(matches!(
+            Game::load_replay("/nonexistent/game.sgr"),
+            Err(SophieError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,2868 @@
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
+
+mod ansi;
+mod error;
+mod oracle;
+mod replay;
+mod rng;
+mod score;
+mod search;
+mod snapshot;
+mod sweep;
+mod transposition;
+#[cfg(feature = "undo-log")]
+mod undo_log;
+pub use error::SophieError;
+pub use oracle::Oracle;
+pub use replay::{Divergence, ReplayError};
+pub use rng::{RngKind, XorShift};
+pub use score::{Score, ScoreWeights};
+pub use search::{
+    prefer_far_policy, uniform_policy, GameTree, Search, SearchOutcome, TreeNode, TreeNodeKind,
+    PROGRESS_INTERVAL,
+};
+pub use snapshot::{SnapshotError, SAVE_VERSION};
+pub use sweep::{
+    compare, cross_check, longest_forced_win_streak, sign_test_p_value, sweep, sweep_parallel,
+    sweep_timed, sweep_until, sweep_with, Comparison, CrossCheck, Disagreement, SweepStats,
+    Timings, MAX_LEN_BUCKET, REORDER_WINDOW,
+};
+pub use transposition::TranspositionTable;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    Ace,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+}
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
+}
+
+/// Cards 0 to 51 are the standard deck, thirteen to a suit; 52 and 53 are
+/// the jokers `Deck::new_with_jokers` adds.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Card(u8);
+
+impl Card {
+    /// The card in plain ASCII, like `10H` or `QS`; jokers are `JK`.
+    pub fn ascii(&self) -> String {
+        match (self.rank(), self.suit()) {
+            (Some(rank), Some(suit)) => format!("{}{}", rank.single_char(), suit.letter()),
+            _ => "JK".to_string(),
+        }
+    }
+    /// Whether this is a joker, which matches any card.
+    pub fn is_wild(&self) -> bool {
+        self.0 >= 52
+    }
+    /// The rank's place in `Rank` order, Ace 0 to King 12, for indexing
+    /// counts without going through `rank`. Jokers have no rank, so don't
+    /// ask one.
+    pub fn rank_index(&self) -> usize {
+        debug_assert!(!self.is_wild(), "jokers have no rank");
+        usize::from(self.0 % 13)
+    }
+    /// The suit's place in `Suit` order, 0 to 3; jokers have none.
+    pub fn suit_index(&self) -> usize {
+        debug_assert!(!self.is_wild(), "jokers have no suit");
+        usize::from(self.0 / 13)
+    }
+    /// The card's rank, or `None` for a joker.
+    pub fn rank(&self) -> Option<Rank> {
+        if self.is_wild() {
+            return None;
+        }
+        Some(match self.0 % 13 {
+            0 => Rank::Ace,
+            1 => Rank::Two,
+            2 => Rank::Three,
+            3 => Rank::Four,
+            4 => Rank::Five,
+            5 => Rank::Six,
+            6 => Rank::Seven,
+            7 => Rank::Eight,
+            8 => Rank::Nine,
+            9 => Rank::Ten,
+            10 => Rank::Jack,
+            11 => Rank::Queen,
+            12 => Rank::King,
+            _ => unreachable!(),
+        })
+    }
+    /// The card's suit, or `None` for a joker.
+    pub fn suit(&self) -> Option<Suit> {
+        match self.0 / 13 {
+            0 => Some(Suit::Clubs),
+            1 => Some(Suit::Diamonds),
+            2 => Some(Suit::Hearts),
+            3 => Some(Suit::Spades),
+            4 if self.is_wild() => None,
+            _ => panic!("Card out of range"),
+        }
+    }
+}
+impl Rank {
+    pub fn single_char(self) -> &'static str {
+        match self {
+            Rank::Ace => "A",
+            Rank::Jack => "J",
+            Rank::Queen => "Q",
+            Rank::King => "K",
+            Rank::Two => "2",
+            Rank::Three => "3",
+            Rank::Four => "4",
+            Rank::Five => "5",
+            Rank::Six => "6",
+            Rank::Seven => "7",
+            Rank::Eight => "8",
+            Rank::Nine => "9",
+            Rank::Ten => "10",
+        }
+    }
+}
+impl Suit {
+    pub fn single_char(self) -> &'static str {
+        match self {
+            Suit::Clubs => "♣",
+            Suit::Diamonds => "♦",
+            Suit::Hearts => "♥",
+            Suit::Spades => "♠",
+        }
+    }
+    /// The letter `FromStr` reads for this suit.
+    pub fn letter(self) -> &'static str {
+        match self {
+            Suit::Clubs => "C",
+            Suit::Diamonds => "D",
+            Suit::Hearts => "H",
+            Suit::Spades => "S",
+        }
+    }
+    pub fn is_red(&self) -> bool {
+        matches!(self, Suit::Diamonds | Suit::Hearts)
+    }
+}
+/// Accepts `A`, `2` to `10`, `T` for ten, `J`, `Q` and `K`.
+impl std::str::FromStr for Rank {
+    type Err = SophieError;
+    fn from_str(s: &str) -> std::result::Result<Self, SophieError> {
+        match s {
+            "A" => Ok(Rank::Ace),
+            "2" => Ok(Rank::Two),
+            "3" => Ok(Rank::Three),
+            "4" => Ok(Rank::Four),
+            "5" => Ok(Rank::Five),
+            "6" => Ok(Rank::Six),
+            "7" => Ok(Rank::Seven),
+            "8" => Ok(Rank::Eight),
+            "9" => Ok(Rank::Nine),
+            "10" | "T" => Ok(Rank::Ten),
+            "J" => Ok(Rank::Jack),
+            "Q" => Ok(Rank::Queen),
+            "K" => Ok(Rank::King),
+            _ => Err(SophieError::Parse(format!("`{}` isn't a rank; expected A, 2-10, T, J, Q or K", s))),
+        }
+    }
+}
+/// Accepts the letters `C`, `D`, `H` and `S`, or the suit symbols.
+impl std::str::FromStr for Suit {
+    type Err = SophieError;
+    fn from_str(s: &str) -> std::result::Result<Self, SophieError> {
+        match s {
+            "C" | "♣" => Ok(Suit::Clubs),
+            "D" | "♦" => Ok(Suit::Diamonds),
+            "H" | "♥" => Ok(Suit::Hearts),
+            "S" | "♠" => Ok(Suit::Spades),
+            _ => Err(SophieError::Parse(format!("`{}` isn't a suit; expected C, D, H, S or a suit symbol", s))),
+        }
+    }
+}
+/// A rank followed by a suit, as `Rank` and `Suit` read them: `10H`, `TH`,
+/// `Q♠`. Jokers aren't read, as there'd be no telling the two apart.
+impl std::str::FromStr for Card {
+    type Err = SophieError;
+    fn from_str(s: &str) -> std::result::Result<Self, SophieError> {
+        let split = s.char_indices().last().map_or(0, |(i, _)| i);
+        let (rank, suit) = s.split_at(split);
+        let rank: Rank = rank.parse()?;
+        let suit: Suit = suit.parse()?;
+        Ok(Card(suit as u8 * 13 + rank as u8))
+    }
+}
+/// Cards sort by suit, then rank. This is a canonical order for showing a
+/// hand of cards; it means nothing for the tableau, where a card's position
+/// decides what it can match, so never sort a tableau to compare positions.
+/// Jokers come last.
+impl Ord for Card {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.is_wild(), self.suit(), self.rank(), self.0).cmp(&(other.is_wild(), other.suit(), other.rank(), other.0))
+    }
+}
+impl PartialOrd for Card {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Debug for Card {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.rank(), self.suit()) {
+            (Some(rank), Some(suit)) => write!(f, "{}{}", rank.single_char(), suit.single_char()),
+            _ => write!(f, "🃏"),
+        }
+    }
+}
+#[derive(Clone)]
+pub struct Deck {
+    //never changes once the deck is made, so clones share it; dealing only
+    //moves `pos`.
+    list: Arc<[Card]>,
+    pos: usize,
+}
+impl Debug for Deck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.undealt()
+                .iter()
+                .map(|x| format!("{:?}", x))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+}
+impl Deck {
+    pub fn new_unshuffled() -> Self {
+        Self::from_list((0..52).map(Card).collect())
+    }
+    /// The standard deck plus two jokers, unshuffled; shuffle it with
+    /// `shuffle_with`.
+    pub fn new_with_jokers() -> Self {
+        Self::from_list((0..54).map(Card).collect())
+    }
+    pub fn new_shuffled() -> Self {
+        let mut d = Self::new_unshuffled();
+        d.shuffle_with(&mut thread_rng());
+        d
+    }
+    /// Shuffles the undealt cards with `rng`; in a fresh deck, that's all of
+    /// them. The same seeded `rng` gives the same order, but only for as long
+    /// as `rand` keeps its shuffle unchanged, so `from_seed` doesn't use this.
+    pub fn shuffle_with<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let mut list = self.list.to_vec();
+        list[self.pos..].shuffle(rng);
+        self.list = list.into();
+    }
+    fn from_list(list: Vec<Card>) -> Self {
+        Self {
+            list: list.into(),
+            pos: 0,
+        }
+    }
+    /// A deck dealt in the order given, first card first. It must be the
+    /// 52 cards once each.
+    pub fn from_cards(cards: Vec<Card>) -> std::result::Result<Self, SophieError> {
+        let mut seen = [false; 52];
+        for c in &cards {
+            match seen.get_mut(usize::from(c.0)) {
+                Some(s) if !*s => *s = true,
+                _ => return Err(SophieError::Parse(format!("deck has {:?} twice or isn't a standard deck", c))),
+            }
+        }
+        if cards.len() != seen.len() {
+            return Err(SophieError::Parse(format!("deck has {} cards, not 52", cards.len())));
+        }
+        Ok(Self::from_list(cards))
+    }
+    /// A deck read from a file of card names, as `Card` parses them,
+    /// separated by whitespace and dealt in the order they're written. For
+    /// replaying a shuffle made somewhere else.
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> std::result::Result<Self, SophieError> {
+        let text = std::fs::read_to_string(path)?;
+        let cards = text.split_whitespace().map(str::parse).collect::<std::result::Result<Vec<Card>, _>>()?;
+        Self::from_cards(cards)
+    }
+    /// A deck shuffled from `seed`. The same seed deals the same cards on
+    /// every build, whatever version of `rand` is in use.
+    pub fn from_seed(seed: u64) -> Self {
+        Self::from_seed_with(seed, RngKind::Xorshift)
+    }
+    /// A deck shuffled from `seed` by the chosen generator.
+    pub fn from_seed_with(seed: u64, kind: RngKind) -> Self {
+        let mut list: Vec<Card> = (0..52).map(Card).collect();
+        kind.shuffle(&mut list, seed);
+        Self::from_list(list)
+    }
+    /// The cards still to be dealt, next one first.
+    pub fn undealt(&self) -> &[Card] {
+        &self.list[self.pos..]
+    }
+    /// Whether every card has been dealt.
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.list.len()
+    }
+    /// Turns the undealt cards over, so they're dealt from the bottom of the
+    /// deck up: the last card of the list comes first.
+    pub fn from_bottom(self) -> Self {
+        let mut list = self.list.to_vec();
+        list[self.pos..].reverse();
+        Self {
+            list: list.into(),
+            pos: self.pos,
+        }
+    }
+    pub fn take_undealt(self) -> Vec<Card> {
+        self.undealt().to_vec()
+    }
+    pub fn draw(&mut self) -> Option<Card> {
+        if self.pos >= self.list.len() {
+            None
+        } else {
+            self.pos += 1;
+            Some(self.list[self.pos - 1])
+        }
+    }
+}
+
+/// Somewhere cards are dealt from.
+pub trait Stock {
+    fn draw(&mut self) -> Option<Card>;
+    /// Number of cards still to come.
+    fn remaining(&self) -> usize;
+}
+impl Stock for Deck {
+    fn draw(&mut self) -> Option<Card> {
+        Deck::draw(self)
+    }
+    fn remaining(&self) -> usize {
+        self.list.len() - self.pos
+    }
+}
+
+/// The variant of the game being played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    /// How far to the left a card may look for a match. The first distance's
+    /// matches are tracked in `PlacedCard::matches_one`, the second's in
+    /// `matches_three`. The classic game uses 1 and 3.
+    pub distances: [MatchDistance; 2],
+    /// Deal from the bottom of the deck rather than the top, whichever
+    /// constructor the deck came in through.
+    pub deal_from_bottom: bool,
+    /// How few piles count as a win once everything is dealt. The classic
+    /// game wants them all in 1. Ignored under `RemovalMode::Discard`, which
+    /// is only won by clearing the tableau.
+    pub win_target: usize,
+    /// The order `find_matches` lists matches in, and so the order the
+    /// solver tries them. It never changes which moves are legal, so
+    /// replays and snapshots don't record it.
+    pub match_order: MatchOrder,
+    /// End play as soon as a deal takes the tableau out to this many piles,
+    /// and stop searching a game, reporting `Result::Aborted`, as soon as
+    /// any line does, to filter out games that balloon. Replays don't
+    /// record it.
+    pub max_tableau_abort: Option<usize>,
+    /// Cards of neighbouring ranks match too, as 6 and 7 do.
+    pub adjacent_ranks: bool,
+    /// With `adjacent_ranks`, whether the ranks wrap round so King and Ace
+    /// are neighbours. Ace and Two always are.
+    pub ace_high: bool,
+    /// What a match does with the two piles.
+    pub removal: RemovalMode,
+}
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            distances: [1, 3],
+            deal_from_bottom: false,
+            win_target: 1,
+            match_order: MatchOrder::default(),
+            max_tableau_abort: None,
+            adjacent_ranks: false,
+            ace_high: false,
+            removal: RemovalMode::default(),
+        }
+    }
+}
+impl Rules {
+    /// Whether, and how, two cards match under these rules.
+    pub fn cards_match(&self, a: Card, b: Card) -> Option<MatchType> {
+        if a.is_wild() || b.is_wild() {
+            Some(MatchType::Wild)
+        } else if a.suit() == b.suit() {
+            Some(MatchType::Suit)
+        } else if a.rank() == b.rank() {
+            Some(MatchType::Rank)
+        } else if self.adjacent_ranks && self.ranks_adjacent(a, b) {
+            Some(MatchType::Adjacent)
+        } else {
+            None
+        }
+    }
+    fn ranks_adjacent(&self, a: Card, b: Card) -> bool {
+        match a.rank_index().abs_diff(b.rank_index()) {
+            1 => true,
+            12 => self.ace_high,
+            _ => false,
+        }
+    }
+}
+/// What happens to the two piles in a match.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RemovalMode {
+    /// The moving pile lands on the one it matched, as in Accordion.
+    #[default]
+    Merge,
+    /// Both piles are thrown away, and the game is won by emptying the
+    /// tableau.
+    Discard,
+}
+/// How `find_matches` orders the matches it finds.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MatchOrder {
+    /// Left to right by position; at one position, the first rules distance
+    /// before the second.
+    #[default]
+    PositionFirst,
+    /// Every match at the first rules distance, left to right, then every
+    /// one at the second.
+    DistanceFirst,
+}
+impl Rules {
+    fn max_distance(&self) -> usize {
+        self.distances.iter().copied().max().unwrap_or(0).into()
+    }
+    /// The most piles a won game can finish with: the win target, or none
+    /// when matched piles are discarded.
+    pub fn winning_piles(&self) -> usize {
+        match self.removal {
+            RemovalMode::Merge => self.win_target,
+            RemovalMode::Discard => 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Game {
+    deck: Deck,
+    rules: Rules,
+    //the seed the deck was shuffled from, if it was seeded, and the
+    //generator that shuffled it.
+    seed: Option<u64>,
+    rng: RngKind,
+    choice_points: usize,
+    //total number of legal moves offered across all choice points.
+    choice_set_total: usize,
+    //deals and matches made, across every line played.
+    steps: usize,
+    //most piles the tableau has held, across every line played.
+    max_tableau_len: usize,
+    tableau: Vec<PlacedCard>,
+    //every move made so far, in order.
+    log: Vec<Move>,
+    //moves taken back by `undo`, most recent last.
+    redo: Vec<Move>,
+    //the deck position at each choice point, when asked to keep them.
+    choice_positions: Option<Vec<usize>>,
+    //whether a dealt card's match flags are set as it lands.
+    check_on_deal: bool,
+    //whether every pile's match flags are current. Dealing without
+    //`check_on_deal` clears it and `recompute_all_matches` sets it again.
+    flags_fresh: bool,
+    //how many more times `undo` may be used, if it's limited.
+    undos_left: Option<usize>,
+    //how many times `undo` has succeeded.
+    undos_used: usize,
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MatchType {
+    Suit,
+    Rank,
+    /// One of the cards is a joker.
+    Wild,
+    /// The ranks are next to each other, under `Rules::adjacent_ranks`.
+    Adjacent,
+}
+pub type MatchDistance = u8;
+#[derive(Debug)]
+pub enum Choices {
+    GameWon,
+    GameLost,
+    /// The tableau reached the rules' `max_tableau_abort`, ending the game.
+    Aborted,
+    ChooseOne(Vec<Match>),
+}
+pub type Match = (usize, MatchDistance);
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Move {
+    Deal,
+    Match(Match),
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// Tried to deal with no cards left in the deck.
+    DeckEmpty,
+    /// The card at that position doesn't match at that distance.
+    NotAMatch(Match),
+    /// The tableau has reached the rules' `max_tableau_abort`, so the game
+    /// is over.
+    Aborted,
+}
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::DeckEmpty => write!(f, "no cards left to deal"),
+            MoveError::Aborted => write!(f, "the game was aborted at its tableau limit"),
+            MoveError::NotAMatch((ix, d)) => {
+                write!(f, "card at {} has no match at distance {}", ix, d)
+            }
+        }
+    }
+}
+impl std::error::Error for MoveError {}
+#[derive(Debug)]
+pub enum StepResult {
+    Dealt(Card),
+    Matched(Match),
+    GameWon,
+    GameLost,
+    /// The last deal took the tableau to the rules' `max_tableau_abort`.
+    Aborted,
+    ChooseOne(Vec<Match>),
+}
+/// What `play_to_choice_with` reports as play runs, for animating it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameEvent {
+    Dealt(Card),
+    /// The pile at `from` was moved onto the pile at `to`, leaving `card` on top.
+    Matched { from: usize, to: usize, card: Card },
+    /// The piles at `from` and `to` were both thrown away, under
+    /// `RemovalMode::Discard`.
+    Discarded { from: usize, to: usize },
+    ReachedChoice(Vec<Match>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlacedCard {
+    //the card on top of the pile; only it takes part in matches.
+    card: Card,
+    //how many cards are in the pile. the ones underneath are never looked
+    //at again, so only their number is kept.
+    #[serde(default = "one")]
+    size: usize,
+    //matches the card at the first rules distance (1 in the classic game).
+    matches_one: bool,
+    //matches the card at the second rules distance (3 in the classic game).
+    matches_three: bool,
+}
+//serde's default for counts that start at one.
+pub(crate) fn one() -> usize {
+    1
+}
+impl PlacedCard {
+    /// The card showing on top of the pile.
+    pub fn top(&self) -> Card {
+        self.card
+    }
+    /// Number of cards in the pile.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    fn flag_char(&self) -> &'static str {
+        match (self.matches_one, self.matches_three) {
+            (false, false) => "_",
+            (true, false) => "S",
+            (false, true) => "L",
+            (true, true) => "B",
+        }
+    }
+}
+impl Debug for PlacedCard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}{}", self.top(), self.flag_char())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    version: u16,
+    pos: usize,
+    tableau: Vec<PlacedCard>,
+    //how much of the move log to keep on restore.
+    #[serde(default)]
+    log_len: usize,
+}
+
+impl Debug for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Deck: {:?}", self.deck)?;
+        writeln!(
+            f,
+            "Tableau: {}",
+            self.tableau
+                .iter()
+                .map(|x| format!("{:?}", x))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+}
+impl Game {
+    /// The board as plain ASCII, for pasting where suit symbols and colour
+    /// won't survive: one line per pile, the undealt cards wrapped thirteen
+    /// to a line, and a legend.
+    pub fn to_ascii(&self) -> String {
+        let [near, far] = self.rules.distances;
+        let mut out = format!("{} piles, {} cards to deal\n", self.tableau.len(), self.deck.undealt().len());
+        out += "  pos  card  size  moves\n";
+        for (ix, p) in self.tableau.iter().enumerate() {
+            let mut moves = Vec::new();
+            if p.matches_one {
+                moves.push(near.to_string());
+            }
+            if p.matches_three {
+                moves.push(far.to_string());
+            }
+            let moves = if moves.is_empty() { "-".to_string() } else { moves.join(",") };
+            out += &format!("  {:>3}  {:<4}  {:>4}  {}\n", ix, p.top().ascii(), p.size, moves);
+        }
+        out += "deck:";
+        for (i, c) in self.deck.undealt().iter().enumerate() {
+            out += if i % 13 == 0 && i > 0 { "\n     " } else { " " };
+            out += &c.ascii();
+        }
+        out += "\n";
+        let moves = match self.rules.removal {
+            RemovalMode::Merge => "the pile can move left by",
+            RemovalMode::Discard => "to the left of a pile it can be discarded with",
+        };
+        out += &format!("moves: the distances ({} or {}) {}. JK is a joker.\n", near, far, moves);
+        out
+    }
+    /// The tableau as an SVG image: a row of cards, each a rounded `<rect>`
+    /// with its rank and suit in red or black, and under each the rules
+    /// distances it can currently match at.
+    pub fn to_svg(&self) -> String {
+        const W: usize = 50;
+        const H: usize = 70;
+        const GAP: usize = 10;
+        let width = GAP + self.tableau.len() * (W + GAP);
+        let height = H + 3 * GAP;
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = width,
+            h = height
+        );
+        let [near, far] = self.rules.distances;
+        for (ix, p) in self.tableau.iter().enumerate() {
+            let x = GAP + ix * (W + GAP);
+            let c = p.top();
+            let colour = if c.suit().is_some_and(|s| s.is_red()) { "#c00" } else { "#000" };
+            out += &format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"6\" fill=\"#fff\" stroke=\"#333\"/>\n",
+                x, GAP, W, H
+            );
+            out += &format!(
+                "  <text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"18\" text-anchor=\"middle\" fill=\"{}\">{:?}</text>\n",
+                x + W / 2,
+                GAP + H / 2 + 6,
+                colour,
+                c
+            );
+            let mut marks = Vec::new();
+            if p.matches_one {
+                marks.push(near.to_string());
+            }
+            if p.matches_three {
+                marks.push(far.to_string());
+            }
+            if !marks.is_empty() {
+                out += &format!(
+                    "  <text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"12\" text-anchor=\"middle\" fill=\"#070\">{}</text>\n",
+                    x + W / 2,
+                    GAP + H + 2 * GAP - 4,
+                    marks.join(",")
+                );
+            }
+        }
+        out += "</svg>\n";
+        out
+    }
+    /// The board as `Debug` shows it, but with red suits coloured red using
+    /// ANSI escapes when `color` is set.
+    pub fn render(&self, color: bool) -> String {
+        let card = |c: &Card| {
+            let s = format!("{:?}", c);
+            if color && c.suit().is_some_and(|s| s.is_red()) {
+                ansi::red(&s)
+            } else {
+                s
+            }
+        };
+        format!(
+            "Deck: {}\nTableau: {}\n",
+            self.deck
+                .undealt()
+                .iter()
+                .map(card)
+                .collect::<Vec<_>>()
+                .join(" "),
+            self.tableau
+                .iter()
+                .map(|x| format!("{}{}", card(&x.top()), x.flag_char()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+}
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<'a> Game {
+    pub fn new() -> Self {
+        Self::from_new_deck(Deck::new_shuffled(), Rules::default())
+    }
+    pub fn from_seed(seed: u64) -> Self {
+        Self::from_seed_with_rules(seed, Rules::default())
+    }
+    /// A classic game dealt from the deck in `path`; see `Deck::from_file`.
+    pub fn from_deck_file<P: AsRef<std::path::Path>>(path: P) -> std::result::Result<Self, SophieError> {
+        Ok(Self::from_new_deck(Deck::from_file(path)?, Rules::default()))
+    }
+    /// Like `from_seed`, but with room for the whole deck reserved in the
+    /// tableau, so dealing never reallocates it.
+    pub fn with_capacity(seed: u64) -> Self {
+        let mut g = Self::from_seed(seed);
+        g.tableau.reserve(g.deck.list.len());
+        g
+    }
+    /// A game dealt from whatever `stock` yields, in order. The stock is drawn
+    /// dry up front, as going back to an earlier position means rewinding
+    /// the deal.
+    pub fn from_stock<S: Stock>(mut stock: S, rules: Rules) -> Self {
+        let mut list = Vec::with_capacity(stock.remaining());
+        while let Some(c) = stock.draw() {
+            list.push(c);
+        }
+        Self::from_new_deck(Deck::from_list(list), rules)
+    }
+    /// A classic game dealt from `seed` by the chosen generator.
+    pub fn from_seed_with(seed: u64, kind: RngKind) -> Self {
+        Self::from_seed_parts(seed, kind, Rules::default())
+    }
+    pub fn from_seed_with_rules(seed: u64, rules: Rules) -> Self {
+        Self::from_seed_parts(seed, RngKind::default(), rules)
+    }
+    fn from_seed_parts(seed: u64, kind: RngKind, rules: Rules) -> Self {
+        let mut g = Self::from_new_deck(Deck::from_seed_with(seed, kind), rules);
+        g.seed = Some(seed);
+        g.rng = kind;
+        g
+    }
+    //a game on a deck as it was shuffled or given; the rules say which end
+    //it's dealt from. `from_deck` wants a deck already in deal order.
+    fn from_new_deck(deck: Deck, rules: Rules) -> Self {
+        let deck = if rules.deal_from_bottom { deck.from_bottom() } else { deck };
+        Self::from_deck(deck, rules)
+    }
+    fn from_deck(deck: Deck, rules: Rules) -> Self {
+        Self {
+            deck,
+            rules,
+            seed: None,
+            rng: RngKind::default(),
+            tableau: Vec::new(),
+            choice_points: 0,
+            choice_set_total: 0,
+            steps: 0,
+            max_tableau_len: 0,
+            log: Vec::new(),
+            redo: Vec::new(),
+            choice_positions: None,
+            check_on_deal: true,
+            flags_fresh: true,
+            undos_left: None,
+            undos_used: 0,
+        }
+    }
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+    /// The piles on the table, left to right.
+    pub fn tableau(&self) -> &[PlacedCard] {
+        &self.tableau
+    }
+    /// The top card of each pile, left to right.
+    pub fn tableau_cards(&self) -> Vec<Card> {
+        self.tableau.iter().map(PlacedCard::top).collect()
+    }
+    /// Each pile as (position, top card, matches at the first rules distance,
+    /// matches at the second), left to right. These are the stored flags, so
+    /// without `check_on_deal` they're only as fresh as the last
+    /// `recompute_all_matches`.
+    pub fn enumerate(&self) -> impl Iterator<Item = (usize, Card, bool, bool)> + '_ {
+        self.tableau
+            .iter()
+            .enumerate()
+            .map(|(ix, p)| (ix, p.card, p.matches_one, p.matches_three))
+    }
+    /// The cards still to be dealt, in the order they'll arrive.
+    pub fn stock_order(&self) -> &[Card] {
+        self.deck.undealt()
+    }
+    /// How many of each rank, Ace to King, are still to be dealt. Jokers
+    /// have no rank, so aren't counted.
+    pub fn remaining_rank_counts(&self) -> [usize; 13] {
+        let mut counts = [0; 13];
+        for c in self.deck.undealt().iter().filter(|c| !c.is_wild()) {
+            counts[c.rank_index()] += 1;
+        }
+        counts
+    }
+    /// How many of each suit, in `Suit` order, are still to be dealt.
+    /// Jokers aren't counted.
+    pub fn remaining_suit_counts(&self) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for c in self.deck.undealt().iter().filter(|c| !c.is_wild()) {
+            counts[c.suit_index()] += 1;
+        }
+        counts
+    }
+    /// Every deal and match made so far, in order.
+    pub fn moves(&self) -> &[Move] {
+        &self.log
+    }
+    /// How many pile tops there are of each rank, Ace first. Jokers aren't
+    /// counted.
+    pub fn tableau_ranks(&self) -> [usize; 13] {
+        let mut counts = [0; 13];
+        for p in self.tableau.iter().filter(|p| !p.card.is_wild()) {
+            counts[p.card.rank_index()] += 1;
+        }
+        counts
+    }
+    /// How many pile tops there are of each suit, in `Suit` order. Jokers
+    /// aren't counted.
+    pub fn tableau_suits(&self) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for p in self.tableau.iter().filter(|p| !p.card.is_wild()) {
+            counts[p.card.suit_index()] += 1;
+        }
+        counts
+    }
+    /// The matches made so far, by kind.
+    pub fn match_frequency(&self) -> MatchFrequency {
+        let mut freq = MatchFrequency::default();
+        freq.add_moves(&self.log);
+        freq
+    }
+    pub fn save_game(&'a self) -> SavedGame {
+        SavedGame {
+            version: SAVE_VERSION,
+            pos: self.deck.pos,
+            tableau: self.tableau.clone(),
+            log_len: self.log.len(),
+        }
+    }
+    pub fn restore(&'a mut self, saved: SavedGame) {
+        self.deck.pos = saved.pos;
+        self.tableau = saved.tableau;
+        //without `check_on_deal` there's no telling how fresh the saved flags are.
+        self.flags_fresh = self.check_on_deal;
+        self.log.truncate(saved.log_len);
+        self.redo.clear();
+    }
+    /// Deals the next card, or `None` if the deck is empty or the game was
+    /// aborted at the rules' `max_tableau_abort`.
+    pub fn deal_card(&mut self) -> Option<()> {
+        if self.is_aborted() {
+            return None;
+        }
+        self.deal_unlogged()?;
+        self.log.push(Move::Deal);
+        self.redo.clear();
+        Some(())
+    }
+    //`deal_card` without touching the move log.
+    fn deal_unlogged(&mut self) -> Option<()> {
+        let c = self.deck.draw()?;
+        self.tableau.push(PlacedCard{card: c, size: 1, matches_one: false, matches_three: false});
+        if self.check_on_deal {
+            self.check_matches_at(self.tableau.len() - 1);
+        } else {
+            self.flags_fresh = false;
+        }
+        self.max_tableau_len = self.max_tableau_len.max(self.tableau.len());
+        self.steps += 1;
+        Some(())
+    }
+    pub fn remove_card(&mut self, ix: usize) -> Card {
+        //removing card at ix: cards shift left, so the ones now at ix up to
+        //ix + max distance - 1 have new partners to reconsider.
+        let c = self.tableau.remove(ix);
+        for ix in ix..ix + self.rules.max_distance() {
+            self.check_matches_at(ix);
+        }
+        c.card
+    }
+
+    pub fn place_card(&mut self, c: Card, ix: usize) {
+        //placing a card at ix (overwrite) or on the end. the card itself and
+        //anything up to max distance to its right may now match differently.
+        self.tableau[ix] = PlacedCard{card: c, size: 1, matches_three: false, matches_one: false};
+        for ix in ix..=ix + self.rules.max_distance() {
+            self.check_matches_at(ix);
+        }
+    }
+    /// The distances at which the card at `ix` currently has a match, which
+    /// is nothing if `ix` is off the end of the tableau.
+    pub fn matches_at(&self, ix: usize) -> Vec<MatchDistance> {
+        if !self.flags_fresh {
+            return self.rechecked().matches_at(ix);
+        }
+        match self.tableau.get(ix) {
+            Some(c) => [c.matches_one, c.matches_three]
+                .iter()
+                .zip(self.rules.distances.iter())
+                .filter(|(&m, _)| m)
+                .map(|(_, &d)| d)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+    /// The positions the pile at `ix` can legally be moved onto.
+    pub fn destinations_for(&self, ix: usize) -> Vec<usize> {
+        self.matches_at(ix)
+            .into_iter()
+            .map(|d| ix - usize::from(d))
+            .collect()
+    }
+    //cards dealt without `check_on_deal` leave the stored flags stale until
+    //the next `recompute_all_matches`, so till then the move queries answer
+    //from a rechecked copy instead.
+    fn rechecked(&self) -> Game {
+        let mut g = self.clone_for_search();
+        g.recompute_all_matches();
+        g
+    }
+    /// Recomputes every card's match flags from scratch, for boards whose
+    /// flags can't be trusted, such as ones loaded from outside.
+    pub fn recompute_all_matches(&mut self) {
+        for ix in 0..self.tableau.len() {
+            self.check_matches_at(ix);
+        }
+        self.flags_fresh = true;
+    }
+    /// The rules distances that stay inside the tableau when looking left
+    /// from `ix`. A card can only ever match at these: in the classic game
+    /// position 0 has none, positions 1 and 2 only have distance 1, and
+    /// from position 3 on both distances are possible.
+    pub fn possible_distances_at(&self, ix: usize) -> Vec<MatchDistance> {
+        self.rules
+            .distances
+            .iter()
+            .copied()
+            .filter(|&d| usize::from(d) <= ix)
+            .collect()
+    }
+    fn check_matches_at(&mut self, ix: usize) {
+        //card at ix has just changed. check for new matches going left.
+        //a distance that would reach past position 0 can't match.
+        let n = self.tableau.len();
+        if ix >= n {
+            return;
+        }
+        let a = &self.tableau[ix];
+        let [m1, m3] = self.rules.distances.map(|d| {
+            ix.checked_sub(d.into())
+                .and_then(|j| self.rules.cards_match(a.top(), self.tableau[j].top()))
+                .is_some()
+        });
+        let x = self.tableau.get_mut(ix).unwrap();
+        x.matches_one = m1;
+        x.matches_three = m3;
+    }
+    /// The pile the one at `ix` matches `distance` to its left, and how,
+    /// or `None` if it doesn't or `distance` isn't one of the rules'.
+    pub fn match_info_at(&self, ix: usize, distance: MatchDistance) -> Option<(usize, MatchType)> {
+        if !self.rules.distances.contains(&distance) {
+            return None;
+        }
+        let partner = ix.checked_sub(distance.into())?;
+        let kind = self.rules.cards_match(self.tableau.get(ix)?.top(), self.tableau[partner].top())?;
+        Some((partner, kind))
+    }
+    /// Every legal match, in the rules' `match_order`. The order is fixed for
+    /// a given position, so searches repeat exactly.
+    pub fn find_matches(&self) -> Vec<Match> {
+        if !self.flags_fresh {
+            return self.rechecked().find_matches();
+        }
+        let mut ans = Vec::new();
+        match self.rules.match_order {
+            MatchOrder::PositionFirst => {
+                for (ix, c) in self.tableau.iter().enumerate() {
+                    if c.matches_one{
+                        ans.push((ix, self.rules.distances[0]));
+                    }
+                    if c.matches_three {
+                        ans.push((ix, self.rules.distances[1]));
+                    }
+                }
+            }
+            MatchOrder::DistanceFirst => {
+                for (ix, c) in self.tableau.iter().enumerate() {
+                    if c.matches_one {
+                        ans.push((ix, self.rules.distances[0]));
+                    }
+                }
+                for (ix, c) in self.tableau.iter().enumerate() {
+                    if c.matches_three {
+                        ans.push((ix, self.rules.distances[1]));
+                    }
+                }
+            }
+        }
+        ans
+    }
+    /// Number of legal moves, i.e. `find_matches().len()`, without building the list.
+    pub fn legal_move_count(&self) -> usize {
+        if !self.flags_fresh {
+            return self.rechecked().legal_move_count();
+        }
+        self.tableau
+            .iter()
+            .map(|c| c.matches_one as usize + c.matches_three as usize)
+            .sum()
+    }
+    /// The legal moves as a bitmask, without allocating: bit `2 * ix` is set
+    /// if the pile at `ix` can move the rules' first distance, and bit
+    /// `2 * ix + 1` if it can move the second. `None` if the tableau holds
+    /// more than 64 piles, which only a game from `from_stock` can deal out,
+    /// as the mask has no room for them. Cards dealt without `check_on_deal`
+    /// are an exception to not allocating: until `recompute_all_matches`,
+    /// this answers from a rechecked copy of the game, as the other move
+    /// queries do.
+    pub fn legal_moves_bitmask(&self) -> Option<u128> {
+        if !self.flags_fresh {
+            return self.rechecked().legal_moves_bitmask();
+        }
+        if self.tableau.len() > 64 {
+            return None;
+        }
+        Some(self.tableau.iter().enumerate().fold(0, |mask, (ix, c)| {
+            mask | u128::from(c.matches_one) << (2 * ix) | u128::from(c.matches_three) << (2 * ix + 1)
+        }))
+    }
+    /// The moves set in a `legal_moves_bitmask`, in position order whatever
+    /// the rules' `match_order`.
+    pub fn bitmask_matches(&self, mut mask: u128) -> impl Iterator<Item = Match> {
+        let distances = self.rules.distances;
+        std::iter::from_fn(move || {
+            if mask == 0 {
+                return None;
+            }
+            let bit = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            Some((bit / 2, distances[bit % 2]))
+        })
+    }
+    /// A cheap lower bound on the piles this game can end with.
+    ///
+    /// A card can only leave the top of the tableau by being covered, and can
+    /// only be covered by, or move onto, a card it matches under the rules.
+    /// So a card with no such partner among the tops and the undealt cards
+    /// ends up a pile of its own, and whatever else is left makes at least
+    /// one more pile. Under `RemovalMode::Discard` the rest can vanish, but
+    /// only two at a time, so an odd number of them leaves one behind. A
+    /// lost game can't do better than it has.
+    pub fn min_piles_lower_bound(&self) -> usize {
+        if self.is_lost() {
+            return self.tableau.len();
+        }
+        let pool = self.remaining_cards();
+        let stranded = pool.iter().filter(|c| self.is_dead(c, &pool)).count();
+        let rest = pool.len() - stranded;
+        stranded
+            + match self.rules.removal {
+                RemovalMode::Merge => usize::from(rest > 0),
+                RemovalMode::Discard => rest % 2,
+            }
+    }
+    /// Positions of the piles whose top card matches no other top or
+    /// undealt card under the rules. Nothing can ever move onto these piles, and
+    /// they can never move.
+    pub fn dead_cards(&self) -> Vec<usize> {
+        let pool = self.remaining_cards();
+        self.tableau
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| self.is_dead(&p.card, &pool))
+            .map(|(ix, _)| ix)
+            .collect()
+    }
+    //the cards still in play: the pile tops, then the undealt cards.
+    fn remaining_cards(&self) -> Vec<Card> {
+        self.tableau
+            .iter()
+            .map(PlacedCard::top)
+            .chain(self.deck.undealt().iter().copied())
+            .collect()
+    }
+    fn is_dead(&self, c: &Card, pool: &[Card]) -> bool {
+        !pool
+            .iter()
+            .any(|o| o != c && self.rules.cards_match(*o, *c).is_some())
+    }
+    fn make_match(&mut self, m: Match) {
+        self.match_unlogged(m);
+        self.log.push(Move::Match(m));
+        self.redo.clear();
+    }
+    //`make_match` without touching the move log.
+    fn match_unlogged(&mut self, m: Match) {
+        self.steps += 1;
+        let from = m.0;
+        let d : usize = m.1.into();
+        let to: usize = m.0 - d;
+        if self.rules.removal == RemovalMode::Discard {
+            //both go. everything from `to` on shifts left, so the positions
+            //that now look back across either gap need checking.
+            self.tableau.remove(from);
+            self.tableau.remove(to);
+            for ix in (to..to + self.rules.max_distance()).chain(from - 1..from - 1 + self.rules.max_distance()) {
+                self.check_matches_at(ix);
+            }
+            return;
+        }
+        //the whole pile moves, landing on top of the one at `to`.
+        let moved = self.tableau.remove(from);
+        let target = &mut self.tableau[to];
+        target.card = moved.card;
+        target.size += moved.size;
+        //only three kinds of position can see a different card now: `to`
+        //itself, the ones looking back at `to` from before `from`, and the
+        //ones from `from` on that now look back past the gap. doing just
+        //these is the same as remove_card then place_card, without the
+        //repeats and the positions neither change affects.
+        self.check_matches_at(to);
+        for d in self.rules.distances {
+            let ix = to + usize::from(d);
+            if ix < from {
+                self.check_matches_at(ix);
+            }
+        }
+        for ix in from..from + self.rules.max_distance() {
+            self.check_matches_at(ix);
+        }
+    }
+    pub fn make_choice(&mut self, m: Match) {
+        self.make_match(m);
+    }
+    /// The same game with the suits renamed so they first appear in the
+    /// deck in `Suit` order: the first suit dealt becomes clubs, the next new
+    /// one diamonds, and so on. Matching only asks whether two suits are the
+    /// same, so this plays exactly as the original does, and deals that
+    /// differ only by which suit is which come out identical. The copy has no
+    /// seed, as the seed no longer deals its deck.
+    pub fn canonicalize_suits(&self) -> Game {
+        let mut relabel = [None; 4];
+        let mut next = 0;
+        for c in self.deck.list.iter().filter(|c| !c.is_wild()) {
+            let s = c.suit_index();
+            if relabel[s].is_none() {
+                relabel[s] = Some(next);
+                next += 1;
+            }
+        }
+        let map = |c: Card| {
+            if c.is_wild() {
+                c
+            } else {
+                let s = relabel[c.suit_index()].unwrap_or(c.suit_index());
+                Card((s * 13 + c.rank_index()) as u8)
+            }
+        };
+        let mut g = self.clone();
+        g.seed = None;
+        g.deck.list = self.deck.list.iter().map(|&c| map(c)).collect();
+        for p in &mut g.tableau {
+            p.card = map(p.card);
+        }
+        g
+    }
+    /// A copy to explore from, sharing the deck's cards with this game and
+    /// leaving the move log behind, so `moves` on it starts out empty.
+    pub fn clone_for_search(&self) -> Game {
+        Game {
+            deck: self.deck.clone(),
+            rules: self.rules.clone(),
+            seed: self.seed,
+            rng: self.rng,
+            choice_points: self.choice_points,
+            choice_set_total: self.choice_set_total,
+            steps: self.steps,
+            max_tableau_len: self.max_tableau_len,
+            tableau: self.tableau.clone(),
+            log: Vec::new(),
+            redo: Vec::new(),
+            choice_positions: self.choice_positions.clone(),
+            check_on_deal: self.check_on_deal,
+            flags_fresh: self.flags_fresh,
+            undos_left: self.undos_left,
+            undos_used: self.undos_used,
+        }
+    }
+    /// Each legal match from here, paired with a snapshot of the position
+    /// before it's made. Restoring a snapshot and making its match follows
+    /// that branch, so a player can go back and choose differently.
+    pub fn branches(&self) -> Vec<(Match, SavedGame)> {
+        self.find_matches()
+            .into_iter()
+            .map(|m| (m, self.save_game()))
+            .collect()
+    }
+    /// `choices` in words, one match per line, like
+    /// "move K♠ (pos 7) onto Q♠ (pos 4) at distance 3". The positions are
+    /// read from this game, so describe choices before making any of them.
+    pub fn describe_choices(&self, choices: &Choices) -> String {
+        match choices {
+            Choices::GameWon => "game won".to_string(),
+            Choices::GameLost => format!("game lost with {} piles", self.tableau.len()),
+            Choices::Aborted => format!("game aborted at {} piles", self.tableau.len()),
+            Choices::ChooseOne(c) => c
+                .iter()
+                .map(|&(ix, d)| {
+                    let to = ix - usize::from(d);
+                    let (verb, with) = match self.rules.removal {
+                        RemovalMode::Merge => ("move", "onto"),
+                        RemovalMode::Discard => ("discard", "with"),
+                    };
+                    format!(
+                        "{} {:?} (pos {}) {} {:?} (pos {}) at distance {}",
+                        verb,
+                        self.tableau[ix].top(),
+                        ix,
+                        with,
+                        self.tableau[to].top(),
+                        to,
+                        d
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+    /// Makes a move after checking it's legal. Unlike `play_to_choice`, this
+    /// doesn't insist on matching before dealing.
+    pub fn apply(&mut self, m: Move) -> std::result::Result<(), SophieError> {
+        Ok(self.apply_move(m)?)
+    }
+    /// Makes each of `moves` in turn as `apply` does, then returns the
+    /// choices from where that leaves the game, as `current_choices` sees
+    /// them. Stops at the first illegal move, returning its index in `moves`
+    /// with why; the moves before it stay made.
+    pub fn apply_all(&mut self, moves: &[Move]) -> std::result::Result<Choices, (usize, MoveError)> {
+        for (ix, &m) in moves.iter().enumerate() {
+            self.apply_move(m).map_err(|e| (ix, e))?;
+        }
+        Ok(self.current_choices())
+    }
+    /// Takes back the last move, returning false if there's none to take
+    /// back or the `max_undos` budget is spent. The position is rebuilt by
+    /// replaying the move log from the start of the deal, so a game picked
+    /// up mid-deal from a snapshot can't undo past where it was picked up.
+    pub fn undo(&mut self) -> bool {
+        if self.undos_left == Some(0) {
+            return false;
+        }
+        let deals = self.log.iter().filter(|&&m| m == Move::Deal).count();
+        if deals != self.deck.pos {
+            return false;
+        }
+        let last = match self.log.pop() {
+            Some(m) => m,
+            None => return false,
+        };
+        let mut redo = std::mem::take(&mut self.redo);
+        redo.push(last);
+        let deck = Deck {
+            list: self.deck.list.clone(),
+            pos: 0,
+        };
+        let mut g = Game::from_deck(deck, self.rules.clone());
+        for &m in &self.log {
+            g.apply_move(m).expect("a game's own moves replay");
+        }
+        self.deck = g.deck;
+        self.tableau = g.tableau;
+        //the replay checked every card as it was dealt.
+        self.flags_fresh = true;
+        self.redo = redo;
+        if let Some(left) = &mut self.undos_left {
+            *left -= 1;
+        }
+        self.undos_used += 1;
+        true
+    }
+    /// Allows only `max_undos` more calls to `undo` to succeed, for a
+    /// challenge game. Undos are unlimited until this is called.
+    pub fn set_max_undos(&mut self, max_undos: usize) {
+        self.undos_left = Some(max_undos);
+    }
+    /// How many times `undo` has taken a move back.
+    pub fn undos_used(&self) -> usize {
+        self.undos_used
+    }
+    /// How many more times `undo` can be used, or `None` if it's unlimited.
+    pub fn undos_left(&self) -> Option<usize> {
+        self.undos_left
+    }
+    /// Makes the move most recently taken back by `undo` again, returning
+    /// false if there's none. Any other move clears what can be redone.
+    pub fn redo(&mut self) -> bool {
+        let m = match self.redo.pop() {
+            Some(m) => m,
+            None => return false,
+        };
+        let rest = std::mem::take(&mut self.redo);
+        self.apply_move(m).expect("an undone move is legal again");
+        self.redo = rest;
+        true
+    }
+    /// Whether making `path` from here, every move legal, ends in a win.
+    /// Plays on a copy, so this game is left as it is.
+    pub fn verify_win(&self, path: &[Move]) -> bool {
+        let mut g = self.clone();
+        path.iter().all(|&m| g.apply_move(m).is_ok()) && g.is_won()
+    }
+    pub(crate) fn apply_move(&mut self, m: Move) -> std::result::Result<(), MoveError> {
+        if self.is_aborted() {
+            return Err(MoveError::Aborted);
+        }
+        match m {
+            Move::Deal => self.deal_card().ok_or(MoveError::DeckEmpty),
+            Move::Match(m) => {
+                if self.find_matches().contains(&m) {
+                    self.make_match(m);
+                    Ok(())
+                } else {
+                    Err(MoveError::NotAMatch(m))
+                }
+            }
+        }
+    }
+
+    /// Everything is dealt and it's down to the rules' `winning_piles`, one
+    /// in the classic game. Reaching the target with cards still in the
+    /// deck isn't a win yet: play carries on by dealing.
+    pub fn is_won(&self) -> bool {
+        self.deck.is_empty() && self.tableau.len() <= self.rules.winning_piles()
+    }
+    /// Everything is dealt, more piles than the win target are left, and
+    /// nothing matches.
+    pub fn is_lost(&self) -> bool {
+        self.deck.is_empty()
+            && self.tableau.len() > self.rules.winning_piles()
+            && self.legal_move_count() == 0
+    }
+    /// Whether a deal has taken the tableau to the rules'
+    /// `max_tableau_abort`, which ends the game there and then.
+    pub fn is_aborted(&self) -> bool {
+        self.rules
+            .max_tableau_abort
+            .is_some_and(|max| self.tableau.len() >= max)
+    }
+    /// Whether any move is left: a card to deal, or a match to make. Once
+    /// the deck is empty this is whether matches remain. Under the classic
+    /// rules that's exactly when the game isn't over yet; with a win target
+    /// above one, a won game can still have matches, and play stops anyway.
+    pub fn can_continue(&self) -> bool {
+        !self.is_aborted() && (!self.deck.is_empty() || self.legal_move_count() > 0)
+    }
+    /// Performs a single action: a deal, or a match when it is the only one
+    /// available. Stops without acting when the game is over or there's a
+    /// choice to make; each such stop at a choice counts as a choice point.
+    pub fn step_once(&mut self) -> StepResult {
+        if !self.flags_fresh {
+            self.recompute_all_matches();
+        }
+        if self.is_aborted() {
+            return StepResult::Aborted;
+        }
+        if self.is_won() {
+            return StepResult::GameWon;
+        }
+        if self.is_lost() {
+            return StepResult::GameLost;
+        }
+        let choices = self.find_matches();
+        //println!("{:?}", self);
+        match choices.len() {
+            0 => match self.deal_card() {
+                Some(_) => StepResult::Dealt(self.tableau[self.tableau.len() - 1].top()),
+                None => unreachable!("out of cards and matches is either won or lost"),
+            },
+            1 => {
+                let m = choices[0];
+                self.make_match(m);
+                StepResult::Matched(m)
+            }
+            _ => {
+                self.choice_points += 1;
+                self.choice_set_total += choices.len();
+                if let Some(positions) = &mut self.choice_positions {
+                    positions.push(self.deck.pos);
+                }
+                StepResult::ChooseOne(choices)
+            }
+        }
+    }
+
+    /// How many times play has stopped at a choice between matches.
+    pub fn choice_points(&self) -> usize {
+        self.choice_points
+    }
+    /// How many deals and matches have been made. Like `choice_points` this
+    /// counts work done, so moves later undone or abandoned by restoring an
+    /// earlier position still count; `moves` has the current line.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+    /// Zeroes the choice point count, and the branching factor with it, so a
+    /// reused game can be measured afresh.
+    pub fn reset_choice_points(&mut self) {
+        self.choice_points = 0;
+        self.choice_set_total = 0;
+        if let Some(positions) = &mut self.choice_positions {
+            positions.clear();
+        }
+    }
+    /// Whether `deal_card` looks for matches for the card it deals; it does
+    /// by default. With it off, the flags `enumerate` shows stay unset until
+    /// `recompute_all_matches`, so a board can be built up and then checked
+    /// at once. The move queries (`find_matches`, `matches_at`, `apply` and
+    /// the rest) recheck on demand, and play recomputes before each step, so
+    /// it all plays out the same. Turning it back on leaves the cards dealt
+    /// meanwhile to be rechecked the same way.
+    pub fn set_check_on_deal(&mut self, check: bool) {
+        self.check_on_deal = check;
+    }
+    /// Starts noting how many cards had been dealt at each choice point from
+    /// here on. It's off by default, as a long search meets millions.
+    pub fn track_choice_positions(&mut self) {
+        self.choice_positions.get_or_insert_with(Vec::new);
+    }
+    /// How many cards had been dealt at each choice point since
+    /// `track_choice_positions`, or `None` if it wasn't called.
+    pub fn choice_positions(&self) -> Option<&[usize]> {
+        self.choice_positions.as_deref()
+    }
+    /// The most piles the tableau has held, over every line played from this
+    /// game, restores included.
+    pub fn max_tableau_len(&self) -> usize {
+        self.max_tableau_len.max(self.tableau.len())
+    }
+    /// Mean number of legal moves at the choice points met so far, or 0 if
+    /// there haven't been any.
+    pub fn branching_factor(&self) -> f64 {
+        if self.choice_points == 0 {
+            0.0
+        } else {
+            self.choice_set_total as f64 / self.choice_points as f64
+        }
+    }
+
+    /// The deals and forced matches `play_to_choice` would make from here,
+    /// in order, worked out on a copy so this game doesn't move.
+    pub fn peek_forced_moves(&self) -> Vec<Move> {
+        let mut g = self.clone_for_search();
+        let mut forced = Vec::new();
+        loop {
+            match g.step_once() {
+                StepResult::Dealt(_) => forced.push(Move::Deal),
+                StepResult::Matched(m) => forced.push(Move::Match(m)),
+                _ => return forced,
+            }
+        }
+    }
+    pub fn play_to_choice(&mut self) -> Choices {
+        self.play_to_choice_with(|_| {})
+    }
+    //`play_to_choice`, timing each deal and match.
+    fn play_to_choice_timed(&mut self, timings: &mut Timings) -> Choices {
+        loop {
+            let start = Instant::now();
+            let step = self.step_once();
+            let elapsed = start.elapsed();
+            match step {
+                StepResult::Dealt(_) => timings.dealing += elapsed,
+                StepResult::Matched(_) => timings.matching += elapsed,
+                StepResult::GameWon => return Choices::GameWon,
+                StepResult::GameLost => return Choices::GameLost,
+                StepResult::Aborted => return Choices::Aborted,
+                StepResult::ChooseOne(choices) => {
+                    timings.choices += elapsed;
+                    return Choices::ChooseOne(choices);
+                }
+            }
+        }
+    }
+    /// The choices `play_to_choice` would return here, without moving. Away
+    /// from a choice point the list is what play would do by itself: empty
+    /// when it would deal, one match when that match is forced.
+    pub fn current_choices(&self) -> Choices {
+        if self.is_aborted() {
+            Choices::Aborted
+        } else if self.is_won() {
+            Choices::GameWon
+        } else if self.is_lost() {
+            Choices::GameLost
+        } else {
+            Choices::ChooseOne(self.find_matches())
+        }
+    }
+    /// `play_to_choice` for when nothing needs to see the moves: the forced
+    /// deals and matches aren't added to `moves`, so the log (and with it
+    /// `undo` and `to_replay`) won't account for them.
+    pub fn to_next_choice(&mut self) -> Choices {
+        self.redo.clear();
+        loop {
+            if !self.flags_fresh {
+                self.recompute_all_matches();
+            }
+            if self.is_aborted() {
+                return Choices::Aborted;
+            }
+            if self.is_won() {
+                return Choices::GameWon;
+            }
+            if self.is_lost() {
+                return Choices::GameLost;
+            }
+            let choices = self.find_matches();
+            match choices.len() {
+                0 => {
+                    self.deal_unlogged();
+                }
+                1 => self.match_unlogged(choices[0]),
+                _ => {
+                    self.choice_points += 1;
+                    self.choice_set_total += choices.len();
+                    if let Some(positions) = &mut self.choice_positions {
+                        positions.push(self.deck.pos);
+                    }
+                    return Choices::ChooseOne(choices);
+                }
+            }
+        }
+    }
+    /// Like `play_to_choice`, but tells `on_event` about each deal and match
+    /// as it happens, and about the choice play stops at.
+    pub fn play_to_choice_with(&mut self, mut on_event: impl FnMut(&GameEvent)) -> Choices {
+        loop {
+            match self.step_once() {
+                StepResult::Dealt(card) => on_event(&GameEvent::Dealt(card)),
+                StepResult::Matched((from, d)) if self.rules.removal == RemovalMode::Discard => {
+                    let to = from - usize::from(d);
+                    on_event(&GameEvent::Discarded { from, to });
+                }
+                StepResult::Matched((from, d)) => {
+                    let to = from - usize::from(d);
+                    let card = self.tableau[to].top();
+                    on_event(&GameEvent::Matched { from, to, card });
+                }
+                StepResult::GameWon => return Choices::GameWon,
+                StepResult::GameLost => return Choices::GameLost,
+                StepResult::Aborted => return Choices::Aborted,
+                StepResult::ChooseOne(choices) => {
+                    let event = GameEvent::ReachedChoice(choices);
+                    on_event(&event);
+                    match event {
+                        GameEvent::ReachedChoice(choices) => return Choices::ChooseOne(choices),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Result {
+    AlwaysWin,
+    AlwaysLose,
+    GaveUp,
+    CanWin,
+    /// A line dealt the tableau out to the rules' `max_tableau_abort`.
+    Aborted,
+}
+impl Result {
+    /// Whether at least one line of play was found that wins.
+    pub fn is_winnable(&self) -> bool {
+        matches!(self, Result::AlwaysWin | Result::CanWin)
+    }
+}
+/// How often each kind of match was made.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MatchFrequency {
+    pub by_distance: HashMap<MatchDistance, usize>,
+}
+impl MatchFrequency {
+    /// Counts the matches among `moves`.
+    pub fn add_moves(&mut self, moves: &[Move]) {
+        for m in moves {
+            if let Move::Match((_, d)) = m {
+                *self.by_distance.entry(*d).or_default() += 1;
+            }
+        }
+    }
+    pub fn merge(&mut self, other: &MatchFrequency) {
+        for (&d, &n) in &other.by_distance {
+            *self.by_distance.entry(d).or_default() += n;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GameReport {
+    /// The seed the game was dealt from, if it was seeded.
+    pub seed: Option<u64>,
+    pub choice_points: usize,
+    pub result: Result,
+    /// Fewest piles left on the tableau at any terminal position reached, or
+    /// `None` if the search gave up before reaching one. A win leaves the
+    /// rules' `winning_piles`, which is none at all under
+    /// `RemovalMode::Discard`; for a lost game this shows how close it came.
+    pub final_pile_count: Option<usize>,
+    /// A lost game one match short of winning: one pile more than the rules'
+    /// `winning_piles` when matches merge (two in the classic game), or two
+    /// more under `RemovalMode::Discard`, where a match takes both away.
+    pub near_miss: bool,
+    /// Mean number of legal moves at each choice point, or 0 if there were none.
+    pub branching_factor: f64,
+    /// The most piles on the table at once, over every line searched.
+    pub max_tableau_len: usize,
+    /// The matches made along every winning line found.
+    pub winning_matches: MatchFrequency,
+    /// Moves (deals and matches) in the shortest winning line found, if any.
+    pub shortest_win: Option<usize>,
+    /// The first card dealt, if the deck had any.
+    pub first_card: Option<Card>,
+}
+impl GameReport {
+    /// Whether every line of play wins. The search behind a report never
+    /// prunes, so `AlwaysWin` means it saw every line through to a win; a
+    /// search that gave up proves nothing.
+    pub fn is_forced_win(&self) -> bool {
+        self.result == Result::AlwaysWin
+    }
+    /// The report on one fixed-width line for logs, like
+    /// `seed=00000123 result=CanWin     cp=4821    moves=49  maxlen=18`.
+    /// `moves` is the shortest win found; an unseeded game or one with no
+    /// win shows `-` in place of the number.
+    pub fn summary_line(&self) -> String {
+        let seed = self.seed.map_or_else(|| "-".to_string(), |s| format!("{:08}", s));
+        let moves = self.shortest_win.map_or_else(|| "-".to_string(), |m| m.to_string());
+        format!(
+            "seed={:<8} result={:<10} cp={:<7} moves={:<3} maxlen={}",
+            seed,
+            format!("{:?}", self.result),
+            self.choice_points,
+            moves,
+            self.max_tableau_len
+        )
+    }
+}
+pub fn play_one() -> GameReport {
+    play_game(Game::new())
+}
+/// Choice points `play_game` searches before giving up.
+pub const DEFAULT_BUDGET: usize = 1_000_000;
+/// Searches every line of play from `g`, giving up after `DEFAULT_BUDGET` choice points.
+pub fn play_game(g: Game) -> GameReport {
+    play_game_with_budget(g, DEFAULT_BUDGET)
+}
+/// Searches every line of play from `g`, giving up after `budget` choice points.
+pub fn play_game_with_budget(mut g: Game, budget: usize) -> GameReport {
+    search_game(&mut g, budget, None)
+}
+//`play_game_with_budget`, adding the time spent in each phase to `timings`
+//when it's given.
+pub(crate) fn search_game(g: &mut Game, budget: usize, mut timings: Option<&mut Timings>) -> GameReport {
+    let mut losses = 0;
+    let mut wins = 0;
+    let mut final_pile_count: Option<usize> = None;
+    let mut winning_matches = MatchFrequency::default();
+    let mut shortest_win: Option<usize> = None;
+    let mut to_retry = Vec::new();
+    loop {
+        let choices = match timings.as_deref_mut() {
+            Some(t) => g.play_to_choice_timed(t),
+            None => g.play_to_choice(),
+        };
+        let start = timings.is_some().then(Instant::now);
+        match choices {
+            Choices::GameWon => {
+                wins += 1;
+                final_pile_count = Some(final_pile_count.map_or(g.tableau.len(), |f| f.min(g.tableau.len())));
+                winning_matches.add_moves(&g.log);
+                shortest_win = Some(shortest_win.map_or(g.log.len(), |s| s.min(g.log.len())));
+            }
+            Choices::GameLost => {
+                losses += 1;
+                final_pile_count = Some(final_pile_count.map_or(g.tableau.len(), |f| f.min(g.tableau.len())));
+            }
+            Choices::ChooseOne(c) => {
+                for ch in c {
+                    to_retry.push((g.save_game(), ch));
+                }
+            }
+            Choices::Aborted => {}
+        }
+        if g.choice_points > budget || g.is_aborted() {
+            return GameReport {
+                seed: g.seed,
+                choice_points: g.choice_points,
+                result: if g.is_aborted() { Result::Aborted } else { Result::GaveUp },
+                final_pile_count,
+                near_miss: false,
+                branching_factor: g.branching_factor(),
+                max_tableau_len: g.max_tableau_len(),
+                winning_matches,
+                shortest_win,
+                first_card: g.deck.list.first().copied(),
+            };
+        }
+        let done = match to_retry.pop() {
+            Some(x) => {
+                g.restore(x.0);
+                g.make_choice(x.1);
+                false
+            }
+            None => true,
+        };
+        if let (Some(t), Some(start)) = (timings.as_deref_mut(), start) {
+            t.choices += start.elapsed();
+        }
+        if done {
+            break;
+        }
+    }
+    let result = if losses == 0 {
+        Result::AlwaysWin
+    } else if wins == 0 {
+        Result::AlwaysLose
+    } else {
+        Result::CanWin
+    };
+    //one match leaves one pile fewer when merging, two fewer when discarding.
+    let one_match = match g.rules.removal {
+        RemovalMode::Merge => 1,
+        RemovalMode::Discard => 2,
+    };
+    let near_miss =
+        matches!(result, Result::AlwaysLose) && final_pile_count == Some(g.rules.winning_piles() + one_match);
+    GameReport {
+        seed: g.seed,
+        choice_points: g.choice_points,
+        result,
+        final_pile_count,
+        near_miss,
+        branching_factor: g.branching_factor(),
+        max_tableau_len: g.max_tableau_len(),
+        winning_matches,
+        shortest_win,
+        first_card: g.deck.list.first().copied(),
+    }
+}
+
+/// A `GameReport` with where in the deal the search met its choices.
+#[derive(Debug)]
+pub struct DetailedReport {
+    pub report: GameReport,
+    /// Cards dealt at each choice point, in the order the search met them.
+    pub choice_positions: Vec<usize>,
+}
+impl DetailedReport {
+    /// Choice points counted by deck position, in `bucket`-card ranges keyed
+    /// by where each range starts, to show whether they cluster early or late.
+    pub fn choice_position_histogram(&self, bucket: usize) -> BTreeMap<usize, usize> {
+        let bucket = bucket.max(1);
+        let mut counts = BTreeMap::new();
+        for &pos in &self.choice_positions {
+            *counts.entry(pos / bucket * bucket).or_default() += 1;
+        }
+        counts
+    }
+}
+/// `play_game_with_budget`, also noting the deck position at every choice
+/// point the search meets.
+pub fn play_game_detailed(mut g: Game, budget: usize) -> DetailedReport {
+    g.track_choice_positions();
+    let report = search_game(&mut g, budget, None);
+    let choice_positions = g.choice_positions.unwrap_or_default();
+    DetailedReport {
+        report,
+        choice_positions,
+    }
+}
+
+/// How hard a seed is to solve, from the effort it took to search.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Difficulty {
+    /// `log2(1 + choice_points) * max(branching_factor, 1)`, halved when
+    /// every line wins and infinite when none do. Games with no choice points
+    /// rate 0 unless they're lost, as a lost game is infinite all the same.
+    /// If the search gave up, this is a lower bound.
+    pub rating: f64,
+    pub result: Result,
+    pub choice_points: usize,
+    pub branching_factor: f64,
+}
+impl Game {
+    pub fn difficulty(seed: u64, budget: usize) -> Difficulty {
+        let report = play_game_with_budget(Game::from_seed(seed), budget);
+        let effort =
+            ((1 + report.choice_points) as f64).log2() * report.branching_factor.max(1.0);
+        let rating = match report.result {
+            Result::AlwaysLose => f64::INFINITY,
+            Result::AlwaysWin => effort / 2.0,
+            Result::CanWin | Result::GaveUp | Result::Aborted => effort,
+        };
+        Difficulty {
+            rating,
+            result: report.result,
+            choice_points: report.choice_points,
+            branching_factor: report.branching_factor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a game dealing the cards with these indices, in order.
+    pub(crate) fn game(cards: &[u8]) -> Game {
+        let mut g = Game::new();
+        g.deck = Deck {
+            list: cards.iter().map(|&c| Card(c)).collect(),
+            pos: 0,
+        };
+        g
+    }
+
+    #[test]
+    fn stuck_game_reports_its_final_pile_count() {
+        //AC 2D 3H 4S 5C: no two within reach share a suit or rank, so nothing ever moves.
+        let report = play_game(game(&[0, 14, 28, 42, 4]));
+        assert!(matches!(report.result, Result::AlwaysLose));
+        assert_eq!(report.final_pile_count, Some(5));
+    }
+
+    #[test]
+    fn giving_up_before_any_terminal_reports_no_final_pile_count() {
+        let report = play_game_with_budget(Game::from_seed(2), 0);
+        assert_eq!(report.result, Result::GaveUp);
+        assert_eq!(report.final_pile_count, None);
+    }
+
+    #[test]
+    fn legal_move_count_counts_find_matches() {
+        //an unshuffled deck deals runs of one suit, so there is plenty to choose between.
+        let mut g = game(&(0..52).collect::<Vec<_>>());
+        loop {
+            assert_eq!(g.legal_move_count(), g.find_matches().len());
+            match g.play_to_choice() {
+                Choices::ChooseOne(c) => g.make_choice(c[0]),
+                _ => break,
+            }
+        }
+    }
+
+    #[test]
+    fn only_a_loss_one_pile_short_is_a_near_miss() {
+        //AC 2D, then AC 2D 3H, then AC 2C.
+        assert!(play_game(game(&[0, 14])).near_miss);
+        assert!(!play_game(game(&[0, 14, 28])).near_miss);
+        assert!(!play_game(game(&[0, 1])).near_miss);
+        let discarding = |cards: &[u8]| {
+            let mut g = game(cards);
+            g.rules.removal = RemovalMode::Discard;
+            g
+        };
+        //AC 2D is a match short of the empty tableau; AC 2C 3D ends on one
+        //pile, which no match can clear.
+        assert!(play_game(discarding(&[0, 14])).near_miss);
+        assert!(!play_game(discarding(&[0, 1, 15])).near_miss);
+        assert!(!play_game(discarding(&[0, 14, 28])).near_miss);
+    }
+
+    #[test]
+    fn step_once_takes_one_action_at_a_time() {
+        //AC 2C 3D.
+        let mut g = game(&[0, 1, 15]);
+        let mut seen = Vec::new();
+        loop {
+            let step = g.step_once();
+            seen.push((g.tableau.len(), g.deck.pos));
+            match step {
+                StepResult::Dealt(c) => assert_eq!(c.0, g.tableau[g.tableau.len() - 1].card.0),
+                StepResult::Matched(m) => assert_eq!(m, (1, 1)),
+                StepResult::GameLost => break,
+                other => panic!("unexpected step {:?}", other),
+            }
+        }
+        //deal, deal, the forced match, deal, then the loss changes nothing.
+        assert_eq!(seen, [(1, 1), (2, 2), (1, 2), (2, 3), (2, 3)]);
+        let left: Vec<u8> = g.tableau.iter().map(|p| p.card.0).collect();
+        assert_eq!(left, [1, 15]);
+    }
+
+    #[test]
+    fn possible_distances_stay_inside_the_tableau() {
+        let g = Game::from_seed(0);
+        assert_eq!(g.possible_distances_at(0), Vec::<MatchDistance>::new());
+        assert_eq!(g.possible_distances_at(2), [1]);
+        assert_eq!(g.possible_distances_at(3), [1, 3]);
+    }
+
+    #[test]
+    fn a_seed_with_few_choices_rates_easier_than_a_branchy_one() {
+        //classic deals where every line wins are too rare to pin one here,
+        //so both of these are only won with the right choices.
+        let easy = Game::difficulty(1392, 10_000);
+        let hard = Game::difficulty(235, 10_000);
+        assert_eq!((easy.result, hard.result), (Result::CanWin, Result::CanWin));
+        assert!(easy.choice_points < hard.choice_points);
+        assert!(easy.rating < hard.rating);
+        assert_eq!(Game::difficulty(1392, 10_000), easy);
+    }
+
+    #[test]
+    fn render_colours_only_red_cards_and_only_when_asked() {
+        //5H 6C 7D.
+        let mut g = game(&[30, 5, 19]);
+        g.deal_card();
+        g.deal_card();
+        assert!(!g.render(false).contains('\x1b'));
+        let coloured = g.render(true);
+        assert!(coloured.contains(&ansi::red(&format!("{:?}", Card(30)))));
+        assert!(coloured.contains(&ansi::red(&format!("{:?}", Card(19)))));
+        assert!(!coloured.contains(&ansi::red(&format!("{:?}", Card(5)))));
+    }
+
+    #[test]
+    fn recompute_all_matches_restores_cleared_flags() {
+        let mut g = Game::from_seed(3);
+        for _ in 0..20 {
+            g.deal_card();
+        }
+        let flags = |g: &Game| -> Vec<(bool, bool)> {
+            g.tableau
+                .iter()
+                .map(|p| (p.matches_one, p.matches_three))
+                .collect()
+        };
+        let before = flags(&g);
+        assert!(before.iter().any(|&(one, three)| one || three));
+        for p in &mut g.tableau {
+            p.matches_one = false;
+            p.matches_three = false;
+        }
+        g.recompute_all_matches();
+        assert_eq!(flags(&g), before);
+    }
+
+    #[test]
+    fn branching_factor_is_the_mean_choice_set_size() {
+        //4C can go onto 3C or AC: one choice point of two moves.
+        let report = play_game(game(&[0, 14, 2, 3]));
+        assert_eq!((report.choice_points, report.branching_factor), (1, 2.0));
+
+        let mut g = Game::from_seed(235);
+        let mut sizes = Vec::new();
+        while let Choices::ChooseOne(c) = g.play_to_choice() {
+            sizes.push(c.len());
+            g.make_choice(c[0]);
+        }
+        assert!(sizes.len() > 1);
+        let mean = sizes.iter().sum::<usize>() as f64 / sizes.len() as f64;
+        assert_eq!(g.branching_factor(), mean);
+    }
+
+    #[test]
+    fn undealt_shrinks_as_cards_are_drawn() {
+        let mut deck = Deck::from_seed(4);
+        let bytes = |cards: &[Card]| cards.iter().map(|c| c.0).collect::<Vec<_>>();
+        let all = bytes(deck.undealt());
+        for _ in 0..10 {
+            deck.draw();
+        }
+        assert_eq!(deck.undealt().len(), 42);
+        assert_eq!(bytes(deck.undealt()), &all[10..]);
+        assert_eq!(bytes(&deck.take_undealt()), &all[10..]);
+    }
+
+    #[test]
+    fn cards_sort_by_suit_then_rank() {
+        let mut cards = Deck::from_seed(5).take_undealt();
+        cards.sort();
+        assert_eq!(cards, Deck::new_unshuffled().take_undealt());
+        assert_eq!(cards[..3], [Card(0), Card(1), Card(2)]);
+        assert_eq!(cards[12..14], [Card(12), Card(13)]);
+        assert_eq!(cards[51], Card(51));
+    }
+
+    #[test]
+    fn moving_a_pile_puts_its_card_on_top_and_adds_the_sizes() {
+        //AC 2D 3C 4C.
+        let mut g = game(&[0, 14, 2, 3]);
+        assert!(matches!(g.play_to_choice(), Choices::ChooseOne(_)));
+        g.make_choice((3, 3));
+        assert_eq!(g.tableau[0].top(), Card(3));
+        assert_eq!(g.tableau[0].size(), 2);
+        assert_eq!(g.tableau[1].size(), 1);
+    }
+
+    #[test]
+    fn one_pile_is_only_a_win_once_the_deck_is_empty() {
+        //AC 2C 3D.
+        let mut g = game(&[0, 1, 15]);
+        g.deal_card();
+        g.deal_card();
+        assert!(matches!(g.step_once(), StepResult::Matched((1, 1))));
+        assert_eq!(g.tableau.len(), 1);
+        assert!(!g.deck.is_empty());
+        assert!(matches!(g.step_once(), StepResult::Dealt(_)));
+
+        //AC 2C.
+        let mut g = game(&[0, 1]);
+        g.deal_card();
+        g.deal_card();
+        assert!(matches!(g.step_once(), StepResult::Matched((1, 1))));
+        assert!(g.deck.is_empty());
+        assert!(matches!(g.step_once(), StepResult::GameWon));
+    }
+
+    #[test]
+    fn matches_at_lists_the_live_distances() {
+        //AC 2D 3D 4C.
+        let mut g = game(&[0, 14, 15, 3]);
+        for _ in 0..3 {
+            g.deal_card();
+        }
+        assert_eq!(g.matches_at(2), [1]);
+        assert_eq!(g.matches_at(1), Vec::<MatchDistance>::new());
+        assert_eq!(g.matches_at(9), Vec::<MatchDistance>::new());
+        g.deal_card();
+        assert_eq!(g.matches_at(3), [3]);
+    }
+
+    #[test]
+    fn seed_one_deals_the_same_cards_on_every_build() {
+        //JS 3H AC 2H 6D 8C 8D 7C 5H 2D JH 4D AD 5S 3S 4C 8H 9S 4S 6H KH QS 6S 5D QH 5C
+        //AS KD 9D QD JC 8S 10S AH 3C 9C 10D KC 4H JD 2C 10H 9H 3D 6C KS 7S 7H 10C 2S QC 7D
+        let expected: Vec<u8> = vec![
+            49, 28, 0, 27, 18, 7, 20, 6, 30, 14, 36, 16, 13,
+            43, 41, 3, 33, 47, 42, 31, 38, 50, 44, 17, 37, 4,
+            39, 25, 21, 24, 10, 46, 48, 26, 2, 8, 22, 12, 29,
+            23, 1, 35, 34, 15, 5, 51, 45, 32, 9, 40, 11, 19,
+        ];
+        let dealt: Vec<u8> = Deck::from_seed(1).take_undealt().iter().map(|c| c.0).collect();
+        assert_eq!(dealt, expected);
+    }
+
+    #[test]
+    fn restoring_a_branch_and_choosing_differently_diverges() {
+        //AC 2D 3C 4C.
+        let mut g = game(&[0, 14, 2, 3]);
+        g.play_to_choice();
+        let branches = g.branches();
+        assert_eq!(branches.iter().map(|b| b.0).collect::<Vec<_>>(), [(3, 1), (3, 3)]);
+        let mut taken = Vec::new();
+        for (m, saved) in branches {
+            g.restore(saved);
+            g.make_choice(m);
+            taken.push(g.tableau_cards());
+        }
+        assert_eq!(taken[0], [Card(0), Card(14), Card(3)]);
+        assert_eq!(taken[1], [Card(3), Card(14), Card(2)]);
+    }
+
+    #[test]
+    fn stock_order_starts_with_the_next_card_dealt() {
+        let mut g = Game::from_seed(6);
+        for _ in 0..5 {
+            let next = g.stock_order()[0];
+            g.deal_card();
+            assert_eq!(g.tableau_cards().last(), Some(&next));
+        }
+        assert_eq!(g.stock_order().len(), 47);
+    }
+
+    #[test]
+    fn terminal_predicates_spot_wins_and_losses() {
+        //AC 2C.
+        let mut won = game(&[0, 1]);
+        assert!(matches!(won.play_to_choice(), Choices::GameWon));
+        assert!(won.is_won() && !won.is_lost());
+
+        //AC 2D.
+        let mut lost = game(&[0, 14]);
+        lost.deal_card();
+        assert!(!lost.is_won() && !lost.is_lost());
+        lost.deal_card();
+        assert!(lost.is_lost() && !lost.is_won());
+
+        //everything's dealt, but 2C can still go onto AC.
+        //AC 2C.
+        let mut pending = game(&[0, 1]);
+        pending.deal_card();
+        pending.deal_card();
+        assert!(!pending.is_won() && !pending.is_lost());
+    }
+
+    #[test]
+    fn incremental_flags_agree_with_a_full_recompute() {
+        let variants = [
+            Rules::default(),
+            Rules { distances: [2, 5], ..Rules::default() },
+            Rules { distances: [1, 2], ..Rules::default() },
+        ];
+        let flags = |g: &Game| -> Vec<(bool, bool)> {
+            g.tableau
+                .iter()
+                .map(|p| (p.matches_one, p.matches_three))
+                .collect()
+        };
+        let mut rng = rng::XorShift::new(131);
+        for rules in &variants {
+            for seed in 0..20 {
+                let mut g = Game::from_seed_with_rules(seed, rules.clone());
+                loop {
+                    //any legal move, not just the ones play would make.
+                    let matches = g.find_matches();
+                    let options = matches.len() + usize::from(!g.deck.is_empty());
+                    if options == 0 {
+                        break;
+                    }
+                    match matches.get(rng.below(options as u64) as usize) {
+                        Some(&m) => g.make_match(m),
+                        None => {
+                            g.deal_card();
+                        }
+                    }
+                    let mut fresh = g.clone();
+                    fresh.recompute_all_matches();
+                    assert_eq!(flags(&g), flags(&fresh));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn choice_points_count_up_and_reset() {
+        let mut g = Game::from_seed(1392);
+        assert_eq!(g.choice_points(), 0);
+        for met in 1..=3 {
+            match g.play_to_choice() {
+                Choices::ChooseOne(c) => g.make_choice(c[0]),
+                _ => panic!("seed 1392 has more choices than this"),
+            }
+            assert_eq!(g.choice_points(), met);
+        }
+        g.reset_choice_points();
+        assert_eq!(g.choice_points(), 0);
+    }
+
+    #[test]
+    fn enumerate_yields_each_pile_with_its_flags() {
+        //AC 2D 3D.
+        let mut g = game(&[0, 14, 15]);
+        for _ in 0..3 {
+            g.deal_card();
+        }
+        assert_eq!(
+            g.enumerate().collect::<Vec<_>>(),
+            [
+                (0, Card(0), false, false),
+                (1, Card(14), false, false),
+                (2, Card(15), true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn play_reports_each_event_as_it_happens() {
+        let mut events = Vec::new();
+        //AC 2C 3D 4D.
+        let mut g = game(&[0, 1, 15, 16]);
+        let choices = g.play_to_choice_with(|e| events.push(e.clone()));
+        assert!(matches!(choices, Choices::GameLost));
+        assert_eq!(
+            events,
+            [
+                GameEvent::Dealt(Card(0)),
+                GameEvent::Dealt(Card(1)),
+                GameEvent::Matched { from: 1, to: 0, card: Card(1) },
+                GameEvent::Dealt(Card(15)),
+                GameEvent::Dealt(Card(16)),
+                GameEvent::Matched { from: 2, to: 1, card: Card(16) },
+            ]
+        );
+
+        let mut events = Vec::new();
+        //AC 2D 3C 4C.
+        game(&[0, 14, 2, 3]).play_to_choice_with(|e| events.push(e.clone()));
+        assert_eq!(events.last(), Some(&GameEvent::ReachedChoice(vec![(3, 1), (3, 3)])));
+    }
+
+    #[test]
+    fn ranks_and_suits_parse_every_spelling() {
+        let ranks = ["A", "2", "3", "4", "5", "6", "7", "8", "9", "10", "J", "Q", "K"];
+        for (i, s) in ranks.iter().enumerate() {
+            assert_eq!(s.parse::<Rank>().unwrap() as usize, i);
+        }
+        assert!("T".parse::<Rank>().unwrap() == Rank::Ten);
+        for (letter, symbol, suit) in
+            [("C", "♣", Suit::Clubs), ("D", "♦", Suit::Diamonds), ("H", "♥", Suit::Hearts), ("S", "♠", Suit::Spades)]
+        {
+            assert!(letter.parse::<Suit>().unwrap() == suit);
+            assert!(symbol.parse::<Suit>().unwrap() == suit);
+        }
+        for bad in ["", "1", "11", "a", "Ace"] {
+            assert!(matches!(bad.parse::<Rank>(), Err(SophieError::Parse(_))));
+        }
+        for bad in ["", "c", "X", "CD"] {
+            assert!(matches!(bad.parse::<Suit>(), Err(SophieError::Parse(_))));
+        }
+    }
+
+    //the fewest piles any line from `g` ends with, trying every line.
+    fn fewest_piles(mut g: Game) -> usize {
+        match g.play_to_choice() {
+            Choices::ChooseOne(c) => c
+                .into_iter()
+                .map(|m| {
+                    let mut next = g.clone();
+                    next.make_choice(m);
+                    fewest_piles(next)
+                })
+                .min()
+                .unwrap(),
+            _ => g.tableau.len(),
+        }
+    }
+
+    #[test]
+    fn pile_lower_bound_never_beats_the_best_line() {
+        for rules in [Rules::default(), Rules { distances: [1, 2], ..Rules::default() }] {
+            for seed in 0..60 {
+                let list = Game::from_seed(seed).stock_order()[..12].to_vec();
+                let mut g = Game::from_deck(Deck { list: list.into(), pos: 0 }, rules.clone());
+                loop {
+                    assert!(g.min_piles_lower_bound() <= fewest_piles(g.clone()), "seed {}", seed);
+                    match g.play_to_choice() {
+                        Choices::ChooseOne(c) => g.make_choice(c[0]),
+                        _ => break,
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn with_capacity_reserves_the_whole_deck() {
+        let g = Game::with_capacity(7);
+        assert!(g.tableau.capacity() >= 52);
+        assert!(g.tableau.is_empty());
+        assert_eq!(g.stock_order(), Game::from_seed(7).stock_order());
+    }
+
+    #[test]
+    fn a_card_sharing_nothing_with_the_rest_is_dead() {
+        let dealt = |cards: &[u8]| {
+            let mut g = game(cards);
+            for _ in 0..4 {
+                g.deal_card();
+            }
+            g
+        };
+        //AC 7H 2C 3C.
+        assert_eq!(dealt(&[0, 32, 1, 2]).dead_cards(), [1]);
+        //AC 7H 2C 3C 7S; 7H's one partner is still to be dealt.
+        assert!(dealt(&[0, 32, 1, 2, 45]).dead_cards().is_empty());
+    }
+
+    #[test]
+    fn describe_choices_names_each_card_and_position() {
+        //AC 2D 3C 4C.
+        let mut g = game(&[0, 14, 2, 3]);
+        let choices = g.play_to_choice();
+        assert_eq!(
+            g.describe_choices(&choices),
+            "move 4♣ (pos 3) onto 3♣ (pos 2) at distance 1\nmove 4♣ (pos 3) onto A♣ (pos 0) at distance 3"
+        );
+        assert_eq!(g.describe_choices(&Choices::GameWon), "game won");
+    }
+
+    #[test]
+    fn dealing_from_the_bottom_starts_with_the_last_card() {
+        let rules = Rules { deal_from_bottom: true, ..Rules::default() };
+        let top = Game::from_seed(5).stock_order().to_vec();
+        let stock = Deck::from_list(top.clone());
+        for mut g in [Game::from_seed_with_rules(5, rules.clone()), Game::from_stock(stock, rules.clone())] {
+            assert_eq!(g.stock_order()[0], top[51]);
+            g.deal_card();
+            assert_eq!(g.tableau_cards(), [top[51]]);
+            assert_eq!(g.stock_order().len(), 51);
+            assert_eq!(g.stock_order()[0], top[50]);
+        }
+    }
+
+    #[test]
+    fn verify_win_needs_every_move_legal_and_a_win_at_the_end() {
+        //AC 2C.
+        let g = game(&[0, 1]);
+        assert!(g.verify_win(&[Move::Deal, Move::Deal, Move::Match((1, 1))]));
+        assert!(!g.verify_win(&[Move::Deal, Move::Match((1, 1)), Move::Deal]));
+        assert!(!g.verify_win(&[Move::Deal, Move::Deal]));
+        assert!(g.tableau.is_empty());
+        assert_eq!(g.stock_order().len(), 2);
+    }
+
+    //deals the cards it was given, in order.
+    struct Scripted(std::collections::VecDeque<Card>);
+    impl Stock for Scripted {
+        fn draw(&mut self) -> Option<Card> {
+            self.0.pop_front()
+        }
+        fn remaining(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[test]
+    fn a_game_deals_a_custom_stock_in_order() {
+        //KS 3H 9D QC.
+        let cards = vec![Card(51), Card(28), Card(21), Card(11)];
+        let mut g = Game::from_stock(Scripted(cards.clone().into()), Rules::default());
+        assert_eq!(g.stock_order(), &cards[..]);
+        for _ in 0..4 {
+            g.deal_card();
+        }
+        assert_eq!(g.tableau_cards(), cards);
+        assert!(g.deal_card().is_none());
+    }
+
+    #[test]
+    fn search_clones_share_the_deck_but_deal_alone() {
+        let g = Game::from_seed(3);
+        let mut a = g.clone_for_search();
+        let b = g.clone_for_search();
+        assert!(Arc::ptr_eq(&a.deck.list, &b.deck.list));
+        a.deal_card();
+        assert_eq!(a.deck.pos, 1);
+        assert_eq!(b.deck.pos, 0);
+        assert!(Arc::ptr_eq(&a.deck.list, &g.deck.list));
+    }
+
+    #[test]
+    fn a_win_target_of_two_wins_on_two_piles() {
+        let two = |cards: &[u8]| {
+            let mut g = game(cards);
+            g.rules.win_target = 2;
+            g
+        };
+        //AC 2D.
+        let mut g = two(&[0, 14]);
+        assert!(matches!(g.play_to_choice(), Choices::GameWon));
+        assert_eq!(play_game(two(&[0, 14])).result, Result::AlwaysWin);
+        //AC 2D 3H, which isn't yet a win while cards are still to come.
+        let mut g = two(&[0, 14, 28]);
+        g.deal_card();
+        g.deal_card();
+        assert!(!g.is_won());
+        assert!(matches!(g.play_to_choice(), Choices::GameLost));
+    }
+
+    #[test]
+    fn shuffling_with_the_same_seeded_rng_gives_the_same_order() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let shuffled = |seed| {
+            let mut d = Deck::new_unshuffled();
+            d.shuffle_with(&mut StdRng::seed_from_u64(seed));
+            d.take_undealt()
+        };
+        assert_eq!(shuffled(9), shuffled(9));
+        assert_ne!(shuffled(9), shuffled(10));
+        let mut sorted = shuffled(9);
+        sorted.sort();
+        assert_eq!(sorted, Deck::new_unshuffled().take_undealt());
+    }
+
+    #[test]
+    fn redo_restores_an_undone_move_until_another_is_made() {
+        //AC 2C 3D.
+        let mut g = game(&[0, 1, 15]);
+        for m in [Move::Deal, Move::Deal, Move::Match((1, 1))] {
+            g.apply(m).unwrap();
+        }
+        let after = g.tableau_cards();
+        assert!(g.undo());
+        assert_eq!(g.tableau_cards(), [Card(0), Card(1)]);
+        assert!(g.redo());
+        assert_eq!(g.tableau_cards(), after);
+        assert!(!g.redo());
+
+        assert!(g.undo());
+        g.apply(Move::Deal).unwrap();
+        assert!(!g.redo());
+        assert_eq!(g.tableau_cards(), [Card(0), Card(1), Card(15)]);
+    }
+
+    #[test]
+    fn a_card_matching_at_both_distances_has_two_destinations() {
+        //AC 2D 3C 4C.
+        let mut g = game(&[0, 14, 2, 3]);
+        g.play_to_choice();
+        assert_eq!(g.destinations_for(3), [2, 0]);
+        assert!(g.destinations_for(1).is_empty());
+    }
+
+    #[test]
+    fn match_frequency_counts_each_distance() {
+        //AC 2D 3H 4C 5H.
+        let mut g = game(&[0, 14, 28, 3, 30]);
+        g.play_to_choice();
+        let freq = g.match_frequency();
+        assert_eq!(freq.by_distance, HashMap::from([(1, 1), (3, 1)]));
+        let mut total = freq.clone();
+        total.merge(&freq);
+        assert_eq!(total.by_distance, HashMap::from([(1, 2), (3, 2)]));
+    }
+
+    #[test]
+    fn tableau_ranks_and_suits_count_the_top_cards() {
+        //AC 2D 3C 4C 4H.
+        let mut g = game(&[0, 14, 2, 3, 29]);
+        g.play_to_choice();
+        g.make_choice((3, 3));
+        g.play_to_choice();
+        //4H went onto 4C, which went onto AC; only the tops count.
+        assert_eq!(g.tableau_cards(), [Card(29), Card(14), Card(2)]);
+        assert_eq!(g.tableau_ranks(), [0, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(g.tableau_suits(), [1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn peek_forced_moves_lists_chained_matches_without_playing_them() {
+        //AC 2C 3D 4D.
+        let g = game(&[0, 1, 15, 16]);
+        assert_eq!(
+            g.peek_forced_moves(),
+            [Move::Deal, Move::Deal, Move::Match((1, 1)), Move::Deal, Move::Deal, Move::Match((2, 1))]
+        );
+        assert!(g.tableau.is_empty());
+        assert!(g.moves().is_empty());
+    }
+
+    #[test]
+    fn match_order_decides_the_order_of_the_choices() {
+        let dealt = |match_order| {
+            //AC 2D 3C 4C 4H.
+            let mut g = game(&[0, 14, 2, 3, 29]);
+            g.rules.match_order = match_order;
+            while g.deal_card().is_some() {}
+            g.find_matches()
+        };
+        assert_eq!(dealt(MatchOrder::PositionFirst), [(3, 1), (3, 3), (4, 1)]);
+        assert_eq!(dealt(MatchOrder::DistanceFirst), [(3, 1), (4, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn choice_positions_are_the_cards_dealt_at_each_choice() {
+        let mut g = Game::from_seed(1392);
+        g.track_choice_positions();
+        let mut seen = Vec::new();
+        loop {
+            match g.step_once() {
+                StepResult::ChooseOne(c) => {
+                    seen.push(52 - g.stock_order().len());
+                    g.make_choice(c[0]);
+                }
+                StepResult::GameWon | StepResult::GameLost => break,
+                _ => {}
+            }
+        }
+        assert!(seen.len() > 1);
+        assert_eq!(g.choice_positions(), Some(&seen[..]));
+
+        let detailed = play_game_detailed(Game::from_seed(1392), DEFAULT_BUDGET);
+        assert_eq!(detailed.choice_positions.len(), detailed.report.choice_points);
+        let histogram = detailed.choice_position_histogram(10);
+        assert_eq!(histogram.values().sum::<usize>(), detailed.report.choice_points);
+    }
+
+    #[test]
+    fn to_next_choice_stops_where_play_to_choice_does() {
+        for seed in 0..20 {
+            let mut g = Game::from_seed(seed);
+            loop {
+                let mut lean = g.clone();
+                let choices = g.play_to_choice();
+                let lean_choices = lean.to_next_choice();
+                assert_eq!(format!("{:?}", lean_choices), format!("{:?}", choices));
+                assert_eq!(lean.pack(), g.pack());
+                match choices {
+                    Choices::ChooseOne(c) => g.make_choice(c[c.len() - 1]),
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_joker_matches_any_neighbour() {
+        let rules = Rules::default();
+        let joker = Card(52);
+        assert!(joker.is_wild());
+        assert!(joker.rank().is_none() && joker.suit().is_none());
+        for c in Deck::new_unshuffled().take_undealt() {
+            assert_eq!(rules.cards_match(joker, c), Some(MatchType::Wild));
+            assert_eq!(rules.cards_match(c, joker), Some(MatchType::Wild));
+        }
+        let mut g = Game::from_deck(Deck::from_list(vec![Card(0), joker]), rules);
+        assert!(matches!(g.play_to_choice(), Choices::GameWon));
+        assert_eq!(Deck::new_with_jokers().take_undealt().len(), 54);
+    }
+
+    #[test]
+    fn current_choices_reports_without_moving() {
+        //AC 2D 3C 4C.
+        let mut g = game(&[0, 14, 2, 3]);
+        assert!(matches!(g.current_choices(), Choices::ChooseOne(c) if c.is_empty()));
+        g.play_to_choice();
+        let before = g.pack();
+        assert!(matches!(g.current_choices(), Choices::ChooseOne(c) if c == [(3, 1), (3, 3)]));
+        assert_eq!(g.pack(), before);
+        g.make_choice((3, 1));
+        assert!(matches!(g.current_choices(), Choices::GameLost));
+
+        //AC 2C.
+        let mut g = game(&[0, 1]);
+        g.deal_card();
+        g.deal_card();
+        assert!(matches!(g.current_choices(), Choices::ChooseOne(c) if c == [(1, 1)]));
+    }
+
+    #[test]
+    fn match_info_names_the_partner_and_the_kind() {
+        //AC 2D 3H 4C 4H.
+        let mut g = game(&[0, 14, 28, 3, 29]);
+        while g.deal_card().is_some() {}
+        assert_eq!(g.match_info_at(3, 3), Some((0, MatchType::Suit)));
+        assert_eq!(g.match_info_at(4, 1), Some((3, MatchType::Rank)));
+        assert_eq!(g.match_info_at(4, 3), None);
+        assert_eq!(g.match_info_at(3, 2), None);
+        assert_eq!(g.match_info_at(1, 3), None);
+    }
+
+    #[test]
+    fn without_check_on_deal_flags_wait_for_a_recompute() {
+        //AC 2D 3C 4C 4H.
+        let mut g = game(&[0, 14, 2, 3, 29]);
+        g.set_check_on_deal(false);
+        for _ in 0..4 {
+            g.deal_card();
+        }
+        assert!(g.enumerate().all(|(_, _, one, three)| !one && !three));
+        //the move queries don't trust the flags.
+        assert_eq!(g.find_matches(), [(3, 1), (3, 3)]);
+        g.recompute_all_matches();
+        assert_eq!(g.enumerate().nth(3), Some((3, Card(3), true, true)));
+
+        g.deal_card();
+        assert_eq!(g.enumerate().nth(4), Some((4, Card(29), false, false)));
+        assert_eq!(g.find_matches(), [(3, 1), (3, 3), (4, 1)]);
+        assert_eq!(g.matches_at(4), [1]);
+        //turning the check back on doesn't vouch for flags dealt without it.
+        g.set_check_on_deal(true);
+        assert_eq!(g.legal_move_count(), 3);
+        assert_eq!(g.legal_moves_bitmask(), Some(0b1_1100_0000));
+    }
+
+    #[test]
+    fn undos_stop_once_the_budget_is_spent() {
+        //AC 2D 3H 4S 5C.
+        let mut g = game(&[0, 14, 28, 42, 4]);
+        g.set_max_undos(2);
+        for _ in 0..3 {
+            g.apply(Move::Deal).unwrap();
+        }
+        assert!(g.undo());
+        assert_eq!(g.undos_left(), Some(1));
+        assert!(g.undo());
+        assert_eq!(g.undos_left(), Some(0));
+        assert!(!g.undo());
+        assert_eq!(g.tableau_cards(), [Card(0)]);
+    }
+
+    #[test]
+    fn rank_and_suit_indices_agree_with_the_enums() {
+        for c in Deck::new_unshuffled().take_undealt() {
+            assert_eq!(c.rank_index(), c.rank().unwrap() as usize);
+            assert_eq!(c.suit_index(), c.suit().unwrap() as usize);
+        }
+        assert_eq!(Card(51).rank_index(), 12);
+        assert_eq!(Card(51).suit_index(), 3);
+    }
+
+    #[test]
+    fn a_deck_file_deals_in_the_order_written() {
+        let order = Game::from_seed(11).stock_order().to_vec();
+        let text: Vec<String> = order.iter().map(Card::ascii).collect();
+        let path = std::env::temp_dir().join(format!("sophies-game-deck-{}.txt", std::process::id()));
+        std::fs::write(&path, text.chunks(13).map(|row| row.join(" ")).collect::<Vec<_>>().join("\n")).unwrap();
+        let g = Game::from_deck_file(&path);
+        std::fs::write(&path, text[1..].join(" ")).unwrap();
+        let short = Deck::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut g = g.unwrap();
+        assert_eq!(g.stock_order(), &order[..]);
+        g.deal_card();
+        assert_eq!(g.tableau_cards(), [order[0]]);
+        assert!(short.is_err());
+    }
+
+    #[test]
+    fn suit_permuted_deals_canonicalize_alike() {
+        let seeded = Game::from_seed(1392);
+        let order = seeded.stock_order().to_vec();
+        //clubs to hearts to spades to diamonds to clubs.
+        let swapped = order.iter().map(|c| Card(([2, 0, 3, 1][c.suit_index()] * 13 + c.rank_index()) as u8)).collect();
+        let other = Game::from_deck(Deck::from_list(swapped), Rules::default());
+        assert_ne!(other.stock_order(), &order[..]);
+        let (a, b) = (seeded.canonicalize_suits(), other.canonicalize_suits());
+        assert_eq!(a.stock_order(), b.stock_order());
+        assert_eq!(a.pack(), b.pack());
+        assert_eq!(a.seed(), None);
+        assert_eq!(play_game(a).result, play_game(seeded).result);
+    }
+
+    #[test]
+    fn steps_count_deals_and_matches_alike() {
+        //AC 2C 3D.
+        let mut g = game(&[0, 1, 15]);
+        g.deal_card();
+        assert_eq!(g.steps(), 1);
+        g.deal_card();
+        g.make_choice((1, 1));
+        assert_eq!(g.steps(), 3);
+        assert_eq!(g.choice_points(), 0);
+    }
+
+    #[test]
+    fn can_continue_while_a_deal_or_a_match_is_left() {
+        //AC 2C.
+        let mut g = game(&[0, 1]);
+        g.deal_card();
+        assert!(g.can_continue());
+        //the deck's empty, but 2C can still go onto AC.
+        g.deal_card();
+        assert!(g.can_continue());
+        g.make_choice((1, 1));
+        assert!(!g.can_continue());
+
+        //AC 2D.
+        let mut stuck = game(&[0, 14]);
+        stuck.play_to_choice();
+        assert!(!stuck.can_continue());
+    }
+
+    #[test]
+    fn tableau_cards_on_a_match_free_board_are_the_deal() {
+        //AC 2D 3H 4S 5C.
+        let mut g = game(&[0, 14, 28, 42, 4]);
+        assert!(g.tableau_cards().is_empty());
+        assert!(matches!(g.play_to_choice(), Choices::GameLost));
+        assert_eq!(g.tableau_cards(), [Card(0), Card(14), Card(28), Card(42), Card(4)]);
+    }
+
+    #[test]
+    fn a_tableau_limit_aborts_the_game_when_reached() {
+        let limited = || {
+            //AC 2D 3H 4S 5C 6D 7H.
+            let mut g = game(&[0, 14, 28, 42, 4, 18, 32]);
+            g.rules.max_tableau_abort = Some(5);
+            g
+        };
+        let mut g = limited();
+        assert!(matches!(g.play_to_choice(), Choices::Aborted));
+        assert_eq!(g.tableau().len(), 5);
+        assert!(g.is_aborted());
+        assert!(!g.can_continue());
+        assert!(g.deal_card().is_none());
+        assert!(matches!(g.apply(Move::Deal), Err(SophieError::IllegalMove(MoveError::Aborted))));
+        assert!(matches!(g.step_once(), StepResult::Aborted));
+        assert_eq!(play_game(limited()).result, Result::Aborted);
+        //without the limit the same deal plays out to a loss.
+        let mut unlimited = limited();
+        unlimited.rules.max_tableau_abort = None;
+        assert_eq!(play_game(unlimited).result, Result::AlwaysLose);
+    }
+
+    #[test]
+    fn svg_draws_a_rect_per_pile() {
+        //AC 2D 3H.
+        let mut g = game(&[0, 14, 28]);
+        g.play_to_choice();
+        let svg = g.to_svg();
+        assert!(svg.starts_with("<svg ") && svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect ").count(), 3);
+        assert!(svg.contains(">A♣</text>") && svg.contains(">3♥</text>"));
+    }
+
+    #[test]
+    fn apply_all_stops_at_the_first_illegal_move() {
+        //AC 2C 3D.
+        let mut g = game(&[0, 1, 15]);
+        let choices = g.apply_all(&[Move::Deal, Move::Deal, Move::Match((1, 1))]).unwrap();
+        assert!(matches!(choices, Choices::ChooseOne(c) if c.is_empty()));
+        assert_eq!(g.tableau_cards(), [Card(1)]);
+
+        //AC 2C 3D.
+        let mut g = game(&[0, 1, 15]);
+        let err = g.apply_all(&[Move::Deal, Move::Match((0, 1)), Move::Deal]).unwrap_err();
+        assert_eq!(err, (1, MoveError::NotAMatch((0, 1))));
+        assert_eq!(g.tableau_cards(), [Card(0)]);
+    }
+
+    #[test]
+    fn ace_is_next_to_king_only_when_high() {
+        let low = Rules { adjacent_ranks: true, ..Rules::default() };
+        let high = Rules { ace_high: true, ..low.clone() };
+        for rules in [&low, &high] {
+            assert_eq!(rules.cards_match(Card(0), Card(14)), Some(MatchType::Adjacent));
+            assert_eq!(rules.cards_match(Card(14), Card(0)), Some(MatchType::Adjacent));
+            assert_eq!(rules.cards_match(Card(0), Card(15)), None);
+        }
+        assert_eq!(low.cards_match(Card(51), Card(26)), None);
+        assert_eq!(high.cards_match(Card(51), Card(26)), Some(MatchType::Adjacent));
+        assert_eq!(high.cards_match(Card(26), Card(51)), Some(MatchType::Adjacent));
+        //and not at all without adjacency.
+        assert_eq!(Rules::default().cards_match(Card(0), Card(14)), None);
+    }
+
+    #[test]
+    fn bitmask_holds_exactly_the_legal_matches() {
+        for seed in 0..30 {
+            let mut g = Game::from_seed_with_rules(seed, Rules { match_order: MatchOrder::DistanceFirst, ..Rules::default() });
+            while g.can_continue() {
+                let mut expected = g.find_matches();
+                expected.sort();
+                let mask = g.legal_moves_bitmask().unwrap();
+                assert_eq!(mask.count_ones() as usize, expected.len());
+                assert_eq!(g.bitmask_matches(mask).collect::<Vec<_>>(), expected);
+                if let StepResult::ChooseOne(c) = g.step_once() {
+                    g.make_choice(c[0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bitmask_gives_up_past_sixty_four_piles() {
+        //no two cards one or three apart share a rank or suit.
+        let list = (0..70).map(|i| Card((i % 4 * 13 + i % 13) as u8)).collect();
+        let mut g = Game::from_deck(Deck::from_list(list), Rules::default());
+        for piles in 1..=70 {
+            g.deal_card();
+            assert_eq!(g.legal_moves_bitmask(), if piles <= 64 { Some(0) } else { None });
+        }
+    }
+
+    #[test]
+    fn summary_line_has_fixed_width_fields() {
+        //AC 2C.
+        let mut report = play_game(game(&[0, 1]));
+        assert_eq!(report.summary_line(), "seed=-        result=AlwaysWin  cp=0       moves=3   maxlen=2");
+        report.seed = Some(123);
+        report.result = Result::CanWin;
+        report.choice_points = 4821;
+        report.shortest_win = Some(49);
+        report.max_tableau_len = 18;
+        assert_eq!(report.summary_line(), "seed=00000123 result=CanWin     cp=4821    moves=49  maxlen=18");
+    }
+
+    #[test]
+    fn discarding_removes_both_cards_and_an_empty_tableau_wins() {
+        let discard = Rules { removal: RemovalMode::Discard, ..Rules::default() };
+        let discarding = |cards: &[u8]| {
+            let mut g = game(cards);
+            g.rules = discard.clone();
+            g
+        };
+        //AC 2D 3C 4C.
+        let mut g = discarding(&[0, 14, 2, 3]);
+        let choices = g.play_to_choice();
+        assert_eq!(
+            g.describe_choices(&choices),
+            "discard 4♣ (pos 3) with 3♣ (pos 2) at distance 1\ndiscard 4♣ (pos 3) with A♣ (pos 0) at distance 3"
+        );
+        g.make_choice((3, 3));
+        assert_eq!(g.tableau_cards(), [Card(14), Card(2)]);
+        assert!(g.is_lost());
+
+        //AC 2C.
+        let mut g = discarding(&[0, 1]);
+        assert!(matches!(g.play_to_choice(), Choices::GameWon));
+        assert!(g.tableau.is_empty());
+        //AC 2C 3D: one pile left isn't a win here.
+        assert!(matches!(discarding(&[0, 1, 15]).play_to_choice(), Choices::GameLost));
+        assert_eq!(discard.winning_piles(), 0);
+    }
+
+    #[test]
+    fn remaining_counts_add_up_to_the_undealt_cards() {
+        let mut g = Game::from_seed(8);
+        for dealt in 0..=52 {
+            let left = 52 - dealt;
+            assert_eq!(g.remaining_rank_counts().iter().sum::<usize>(), left);
+            assert_eq!(g.remaining_suit_counts().iter().sum::<usize>(), left);
+            assert_eq!(g.stock_order().len(), left);
+            g.deal_card();
+        }
+        let fresh = Game::from_seed(8);
+        assert_eq!(fresh.remaining_rank_counts(), [4; 13]);
+        assert_eq!(fresh.remaining_suit_counts(), [13; 4]);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{play_game, Game, Result, SophieError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Solved results for the classic rules, keyed by seed and kept in a JSON
+/// file so repeated experiments over the same seeds don't solve them again.
+#[derive(Debug)]
+pub struct Oracle {
+    path: PathBuf,
+    results: BTreeMap<u64, Result>,
+}
+impl Oracle {
+    /// Opens the oracle stored at `path`, starting empty if there's no file yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::result::Result<Self, SophieError> {
+        let path = path.as_ref().to_path_buf();
+        let results = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, results })
+    }
+    /// Writes every result back to the file the oracle was loaded from.
+    pub fn save(&self) -> std::result::Result<(), SophieError> {
+        fs::write(&self.path, serde_json::to_vec(&self.results)?)?;
+        Ok(())
+    }
+    pub fn get(&self, seed: u64) -> Option<Result> {
+        self.results.get(&seed).copied()
+    }
+    pub fn insert(&mut self, seed: u64, result: Result) {
+        self.results.insert(seed, result);
+    }
+    /// The cached result for `seed`, solving and caching it if there isn't
+    /// one. `GaveUp` isn't cached, so a later run can try again.
+    pub fn get_or_solve(&mut self, seed: u64) -> Result {
+        if let Some(r) = self.get(seed) {
+            return r;
+        }
+        let r = play_game(Game::from_seed(seed)).result;
+        if r != Result::GaveUp {
+            self.insert(seed, r);
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_results_survive_a_reload() {
+        let path =
+            std::env::temp_dir().join(format!("sophies-game-oracle-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut oracle = Oracle::load(&path).unwrap();
+        assert_eq!(oracle.get(7), None);
+        oracle.insert(7, Result::CanWin);
+        oracle.insert(8, Result::AlwaysLose);
+        oracle.save().unwrap();
+        let reloaded = Oracle::load(&path);
+        fs::remove_file(&path).unwrap();
+        let reloaded = reloaded.unwrap();
+        assert_eq!(reloaded.get(7), Some(Result::CanWin));
+        assert_eq!(reloaded.get(8), Some(Result::AlwaysLose));
+        assert_eq!(reloaded.get(9), None);
+    }
+}
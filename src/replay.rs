@@ -0,0 +1,332 @@
+//! Sophie's Game Replay (`.sgr`) files: a seed followed by every move made.
+//!
+//! ```text
+//! seed 1234
+//! deal
+//! deal
+//! match 1 1
+//! ```
+//!
+//! Games dealt by a generator other than the default carry an `rng StdRng`
+//! line straight after the seed, and games played under non-classic rules
+//! then a `distances 1 2` line, a `bottom` line if dealt from the bottom of
+//! the deck, a `target 2` line if won on more than one pile, an `adjacent`
+//! line if neighbouring ranks match, an `ace-high` line if Aces sit next to
+//! Kings as well as Twos, and a `discard` line if matched piles are thrown
+//! away.
+use crate::{Game, Move, MoveError, RemovalMode, RngKind, Rules, SophieError, StepResult};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// Only seeded games can be written out, as the seed is what recreates the deck.
+    NoSeed,
+    Parse {
+        line: usize,
+        message: String,
+    },
+    IllegalMove {
+        line: usize,
+        error: MoveError,
+    },
+}
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::NoSeed => write!(f, "game wasn't created from a seed"),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ReplayError::IllegalMove { line, error } => {
+                write!(f, "line {}: illegal move: {}", line, error)
+            }
+        }
+    }
+}
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::IllegalMove { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Where a checked replay stopped following the game's own play.
+#[derive(Debug)]
+pub struct Divergence {
+    /// Index of the first move that isn't what play would do.
+    pub index: usize,
+    /// The game as play would have it: after the forced move, or at the
+    /// choice or end it had reached.
+    pub expected: Game,
+    /// The game after attempting the recorded move instead.
+    pub actual: Game,
+}
+
+fn parse_field<T: std::str::FromStr>(
+    word: Option<&str>,
+    line: usize,
+    what: &str,
+) -> Result<T, ReplayError> {
+    word.and_then(|w| w.parse().ok())
+        .ok_or_else(|| ReplayError::Parse {
+            line,
+            message: format!("expected {}", what),
+        })
+}
+
+impl Game {
+    pub fn to_replay(&self) -> Result<String, SophieError> {
+        let seed = self.seed.ok_or(ReplayError::NoSeed)?;
+        let mut out = format!("seed {}\n", seed);
+        if self.rng != RngKind::default() {
+            out += &format!("rng {:?}\n", self.rng);
+        }
+        if self.rules.distances != Rules::default().distances {
+            let [a, b] = self.rules.distances;
+            out += &format!("distances {} {}\n", a, b);
+        }
+        if self.rules.deal_from_bottom {
+            out += "bottom\n";
+        }
+        if self.rules.win_target != Rules::default().win_target {
+            out += &format!("target {}\n", self.rules.win_target);
+        }
+        if self.rules.adjacent_ranks {
+            out += "adjacent\n";
+        }
+        if self.rules.ace_high {
+            out += "ace-high\n";
+        }
+        if self.rules.removal == RemovalMode::Discard {
+            out += "discard\n";
+        }
+        for m in &self.log {
+            match m {
+                Move::Deal => out += "deal\n",
+                Move::Match((ix, d)) => out += &format!("match {} {}\n", ix, d),
+            }
+        }
+        Ok(out)
+    }
+    pub fn from_replay(text: &str) -> Result<Game, SophieError> {
+        Ok(Self::parse_replay(text)?)
+    }
+    fn parse_replay(text: &str) -> Result<Game, ReplayError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(n, l)| (n + 1, l.trim()))
+            .filter(|(_, l)| !l.is_empty())
+            .peekable();
+        let seed = match lines.next() {
+            Some((n, l)) => match l.split_once(' ') {
+                Some(("seed", s)) => parse_field(Some(s.trim()), n, "a seed number")?,
+                _ => {
+                    return Err(ReplayError::Parse {
+                        line: n,
+                        message: "expected `seed <n>`".to_string(),
+                    })
+                }
+            },
+            None => {
+                return Err(ReplayError::Parse {
+                    line: 1,
+                    message: "replay is empty".to_string(),
+                })
+            }
+        };
+        let mut kind = RngKind::default();
+        if let Some(&(n, l)) = lines.peek() {
+            if let Some(k) = l.strip_prefix("rng ") {
+                kind = parse_field(Some(k.trim()), n, "a generator name")?;
+                lines.next();
+            }
+        }
+        let mut rules = Rules::default();
+        if let Some(&(n, l)) = lines.peek() {
+            if let Some(ds) = l.strip_prefix("distances ") {
+                let mut words = ds.split_whitespace();
+                rules.distances = [
+                    parse_field(words.next(), n, "two distances")?,
+                    parse_field(words.next(), n, "two distances")?,
+                ];
+                let [a, b] = rules.distances;
+                if a == 0 || b == 0 || a == b {
+                    return Err(ReplayError::Parse {
+                        line: n,
+                        message: "match distances must be distinct and positive".to_string(),
+                    });
+                }
+                lines.next();
+            }
+        }
+        if let Some(&(_, "bottom")) = lines.peek() {
+            rules.deal_from_bottom = true;
+            lines.next();
+        }
+        if let Some(&(n, l)) = lines.peek() {
+            if let Some(t) = l.strip_prefix("target ") {
+                rules.win_target = parse_field(Some(t.trim()), n, "a pile count")?;
+                lines.next();
+            }
+        }
+        if let Some(&(_, "adjacent")) = lines.peek() {
+            rules.adjacent_ranks = true;
+            lines.next();
+        }
+        if let Some(&(_, "ace-high")) = lines.peek() {
+            rules.ace_high = true;
+            lines.next();
+        }
+        if let Some(&(_, "discard")) = lines.peek() {
+            rules.removal = RemovalMode::Discard;
+            lines.next();
+        }
+        let mut g = Game::from_seed_parts(seed, kind, rules);
+        for (n, l) in lines {
+            let mut words = l.split_whitespace();
+            let m = match words.next() {
+                Some("deal") => Move::Deal,
+                Some("match") => Move::Match((
+                    parse_field(words.next(), n, "a tableau index")?,
+                    parse_field(words.next(), n, "a match distance")?,
+                )),
+                _ => {
+                    return Err(ReplayError::Parse {
+                        line: n,
+                        message: format!("unknown move `{}`", l),
+                    })
+                }
+            };
+            g.apply_move(m)
+                .map_err(|error| ReplayError::IllegalMove { line: n, error })?;
+        }
+        Ok(g)
+    }
+    /// Replays `moves` on the classic game dealt from `seed`, checking each
+    /// against what play itself would do there: a forced deal or match must
+    /// be exactly that move, and a choice must be one of the legal matches.
+    /// The first move that isn't is reported with both versions of the game.
+    pub fn replay_checked(seed: u64, moves: &[Move]) -> Result<Game, Box<Divergence>> {
+        let mut g = Game::from_seed(seed);
+        for (index, &m) in moves.iter().enumerate() {
+            let mut expected = g.clone();
+            let followed = match (expected.step_once(), m) {
+                (StepResult::Dealt(_), Move::Deal) => true,
+                (StepResult::Matched(forced), Move::Match(made)) => forced == made,
+                (StepResult::ChooseOne(choices), Move::Match(made)) if choices.contains(&made) => {
+                    expected.make_choice(made);
+                    true
+                }
+                _ => false,
+            };
+            if !followed {
+                let mut actual = g;
+                //an illegal move leaves the game as it was.
+                let _ = actual.apply_move(m);
+                return Err(Box::new(Divergence {
+                    index,
+                    expected,
+                    actual,
+                }));
+            }
+            g = expected;
+        }
+        Ok(g)
+    }
+    /// The classic game dealt from `seed` after the first `n` of `moves`,
+    /// or all of them if there are fewer, for scrubbing through a recorded
+    /// game. It's replayed from the deal each time.
+    pub fn seek(seed: u64, moves: &[Move], n: usize) -> Result<Game, SophieError> {
+        let mut g = Game::from_seed(seed);
+        for &m in moves.iter().take(n) {
+            g.apply(m)?;
+        }
+        Ok(g)
+    }
+    pub fn save_replay<P: AsRef<Path>>(&self, path: P) -> Result<(), SophieError> {
+        fs::write(path, self.to_replay()?)?;
+        Ok(())
+    }
+    pub fn load_replay<P: AsRef<Path>>(path: P) -> Result<Game, SophieError> {
+        Self::from_replay(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StepResult;
+
+    #[test]
+    fn saved_replay_reloads_to_the_same_game() {
+        let mut g = Game::from_seed(1);
+        loop {
+            match g.step_once() {
+                StepResult::ChooseOne(c) => g.make_choice(c[c.len() - 1]),
+                StepResult::GameWon | StepResult::GameLost => break,
+                _ => {}
+            }
+        }
+        let path = std::env::temp_dir().join(format!("sophies-game-{}.sgr", std::process::id()));
+        g.save_replay(&path).unwrap();
+        let loaded = Game::load_replay(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.moves(), g.moves());
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", g));
+    }
+
+    #[test]
+    fn replay_rejects_zero_or_equal_distances() {
+        for ds in ["0 3", "2 2"] {
+            let text = format!("seed 1\ndistances {}\ndeal\n", ds);
+            match Game::from_replay(&text) {
+                Err(SophieError::Replay(ReplayError::Parse { line: 2, .. })) => {}
+                other => panic!("{} should be rejected, got {:?}", ds, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn replay_checked_finds_the_first_corrupted_move() {
+        let mut g = Game::from_seed(1);
+        loop {
+            match g.step_once() {
+                StepResult::ChooseOne(c) => g.make_choice(c[0]),
+                StepResult::GameWon | StepResult::GameLost => break,
+                _ => {}
+            }
+        }
+        let mut moves = g.moves().to_vec();
+        assert_eq!(Game::replay_checked(1, &moves).unwrap().pack(), g.pack());
+        let first_match = moves.iter().position(|m| matches!(m, Move::Match(_))).unwrap();
+        moves[first_match] = Move::Deal;
+        let divergence = Game::replay_checked(1, &moves).err().unwrap();
+        assert_eq!(divergence.index, first_match);
+        assert_ne!(divergence.expected.pack(), divergence.actual.pack());
+    }
+
+    #[test]
+    fn seeking_then_playing_the_rest_equals_a_full_replay() {
+        let mut g = Game::from_seed(235);
+        loop {
+            match g.step_once() {
+                StepResult::ChooseOne(c) => g.make_choice(c[c.len() - 1]),
+                StepResult::GameWon | StepResult::GameLost => break,
+                _ => {}
+            }
+        }
+        let moves = g.moves().to_vec();
+        for n in [0, 1, moves.len() / 2, moves.len(), moves.len() + 5] {
+            let mut sought = Game::seek(235, &moves, n).unwrap();
+            assert_eq!(sought.moves(), &moves[..n.min(moves.len())]);
+            for &m in moves.iter().skip(n) {
+                sought.apply(m).unwrap();
+            }
+            assert_eq!(sought.pack(), g.pack());
+        }
+    }
+}
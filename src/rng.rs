@@ -0,0 +1,105 @@
+//! A self-contained PRNG and shuffle for seeded decks. `rand`'s generators
+//! and shuffle are free to change between releases, which would quietly deal
+//! different cards for the same seed; these never change.
+
+use crate::SophieError;
+use rand::rngs::{SmallRng, StdRng};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/// Which generator shuffles a seeded deck. Comparing results across kinds
+/// shows whether a statistic is an artefact of one generator. Only
+/// `Xorshift` is pinned to this crate; the others deal whatever the current
+/// `rand` release makes of the seed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RngKind {
+    StdRng,
+    SmallRng,
+    #[default]
+    Xorshift,
+}
+impl RngKind {
+    pub(crate) fn shuffle<T>(self, list: &mut [T], seed: u64) {
+        match self {
+            RngKind::StdRng => list.shuffle(&mut StdRng::seed_from_u64(seed)),
+            RngKind::SmallRng => list.shuffle(&mut SmallRng::seed_from_u64(seed)),
+            RngKind::Xorshift => shuffle(list, &mut XorShift::new(seed)),
+        }
+    }
+}
+
+impl std::str::FromStr for RngKind {
+    type Err = SophieError;
+    fn from_str(s: &str) -> Result<Self, SophieError> {
+        match s {
+            "StdRng" => Ok(RngKind::StdRng),
+            "SmallRng" => Ok(RngKind::SmallRng),
+            "Xorshift" => Ok(RngKind::Xorshift),
+            _ => Err(SophieError::Parse(format!("unknown generator `{}`", s))),
+        }
+    }
+}
+
+/// xorshift64* seeded through splitmix64.
+pub struct XorShift(u64);
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        //splitmix64 spreads nearby seeds apart and never yields the all-zero
+        //state xorshift gets stuck in, bar one seed that we nudge.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self(if z == 0 { 1 } else { z })
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    /// Uniform in `0..n`, rejecting the top sliver of outputs that would bias
+    /// a plain modulo.
+    pub fn below(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+/// Fisher–Yates shuffle driven by `rng`.
+pub fn shuffle<T>(list: &mut [T], rng: &mut XorShift) {
+    for i in (1..list.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        list.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Deck;
+
+    #[test]
+    fn each_kind_deals_its_own_deck_from_a_seed() {
+        let kinds = [RngKind::StdRng, RngKind::SmallRng, RngKind::Xorshift];
+        for seed in 0..10 {
+            let decks: Vec<_> = kinds
+                .iter()
+                .map(|&k| Deck::from_seed_with(seed, k).take_undealt())
+                .collect();
+            assert_ne!(decks[0], decks[1]);
+            assert_ne!(decks[0], decks[2]);
+            assert_ne!(decks[1], decks[2]);
+            for (&k, deck) in kinds.iter().zip(&decks) {
+                assert_eq!(&Deck::from_seed_with(seed, k).take_undealt(), deck);
+            }
+        }
+    }
+}
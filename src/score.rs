@@ -0,0 +1,100 @@
+//! Points for a finished game, for ranking players on a leaderboard.
+
+use crate::Game;
+use serde::{Deserialize, Serialize};
+
+/// What each part of a game is worth. Penalties are negative weights.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoreWeights {
+    /// Awarded once for winning.
+    pub win: i64,
+    /// Per pile left on the table; negative, so getting closer scores more.
+    pub per_pile: i64,
+    /// Per deal or match made, counting ones later undone.
+    pub per_move: i64,
+    pub per_undo: i64,
+}
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            win: 1000,
+            per_pile: -50,
+            per_move: -1,
+            per_undo: -20,
+        }
+    }
+}
+
+/// The parts of a game that earn or cost points.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Score {
+    pub won: bool,
+    pub piles: usize,
+    pub moves: usize,
+    pub undos: usize,
+}
+impl Score {
+    pub fn total(&self, weights: &ScoreWeights) -> i64 {
+        let win = if self.won { weights.win } else { 0 };
+        win + weights.per_pile * self.piles as i64
+            + weights.per_move * self.moves as i64
+            + weights.per_undo * self.undos as i64
+    }
+}
+
+impl Game {
+    /// The game as it stands, broken down for scoring.
+    pub fn score(&self) -> Score {
+        Score {
+            won: self.is_won(),
+            piles: self.tableau.len(),
+            moves: self.steps(),
+            undos: self.undos_used(),
+        }
+    }
+    /// The points this game is worth under `weights`. Meant for a finished
+    /// game, but scores a game in progress as if it had stopped here.
+    pub fn final_score(&self, weights: ScoreWeights) -> i64 {
+        self.score().total(&weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::game;
+    use crate::Move;
+
+    const WEIGHTS: ScoreWeights = ScoreWeights {
+        win: 100,
+        per_pile: -10,
+        per_move: -2,
+        per_undo: -5,
+    };
+
+    #[test]
+    fn a_short_win_scores_the_bonus_less_its_costs() {
+        //AC 2C.
+        let mut g = game(&[0, 1]);
+        for m in [Move::Deal, Move::Deal, Move::Match((1, 1))] {
+            g.apply(m).unwrap();
+        }
+        assert_eq!(
+            g.score(),
+            Score {
+                won: true,
+                piles: 1,
+                moves: 3,
+                undos: 0
+            }
+        );
+        assert_eq!(g.final_score(WEIGHTS), 100 - 10 - 6);
+        let lost = Score {
+            won: false,
+            piles: 4,
+            moves: 10,
+            undos: 2,
+        };
+        assert_eq!(lost.total(&WEIGHTS), -40 - 20 - 10);
+    }
+}
@@ -0,0 +1,526 @@
+use crate::rng::XorShift;
+use crate::{play_game_with_budget, Choices, Game, Match, Result, SavedGame};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// What an exhaustive search found across every line of play.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchOutcome {
+    AlwaysWin,
+    AlwaysLose,
+    CanWin,
+}
+impl From<SearchOutcome> for Result {
+    fn from(o: SearchOutcome) -> Self {
+        match o {
+            SearchOutcome::AlwaysWin => Result::AlwaysWin,
+            SearchOutcome::AlwaysLose => Result::AlwaysLose,
+            SearchOutcome::CanWin => Result::CanWin,
+        }
+    }
+}
+
+/// Nodes `Game::solve_with_progress` searches between progress reports.
+pub const PROGRESS_INTERVAL: usize = 10_000;
+
+/// A depth-first search over every line of play that can be run a few nodes
+/// at a time, so callers can report progress or stop early. Positions already
+/// expanded at a choice point aren't expanded again.
+pub struct Search {
+    game: Game,
+    to_retry: Vec<(SavedGame, Match)>,
+    visited: HashSet<Vec<u8>>,
+    wins: usize,
+    losses: usize,
+    nodes: usize,
+    done: bool,
+}
+impl Search {
+    pub fn new(game: Game) -> Self {
+        Self {
+            game,
+            to_retry: Vec::new(),
+            visited: HashSet::new(),
+            wins: 0,
+            losses: 0,
+            nodes: 0,
+            done: false,
+        }
+    }
+    /// Number of nodes (runs of play up to a choice or the end) explored so far.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+    /// Explores up to `nodes` more nodes. Returns the outcome once the whole
+    /// tree has been searched, or `None` if there's more to do.
+    pub fn step(&mut self, nodes: usize) -> Option<SearchOutcome> {
+        for _ in 0..nodes {
+            if self.done {
+                break;
+            }
+            match self.game.to_next_choice() {
+                Choices::GameWon => self.wins += 1,
+                Choices::GameLost | Choices::Aborted => self.losses += 1,
+                //no line from here can get down to the win target.
+                Choices::ChooseOne(_)
+                    if self.game.min_piles_lower_bound() > self.game.rules.winning_piles() =>
+                {
+                    self.losses += 1
+                }
+                Choices::ChooseOne(c) => {
+                    if self.visited.insert(self.game.pack()) {
+                        for ch in c {
+                            self.to_retry.push((self.game.save_game(), ch));
+                        }
+                    }
+                }
+            }
+            self.nodes += 1;
+            match self.to_retry.pop() {
+                Some((saved, m)) => {
+                    self.game.restore(saved);
+                    self.game.make_choice(m);
+                }
+                None => self.done = true,
+            }
+        }
+        if self.done {
+            Some(self.outcome())
+        } else {
+            None
+        }
+    }
+    /// Searches to the end.
+    pub fn run(mut self) -> SearchOutcome {
+        loop {
+            if let Some(o) = self.step(usize::MAX) {
+                return o;
+            }
+        }
+    }
+    fn outcome(&self) -> SearchOutcome {
+        if self.losses == 0 {
+            SearchOutcome::AlwaysWin
+        } else if self.wins == 0 {
+            SearchOutcome::AlwaysLose
+        } else {
+            SearchOutcome::CanWin
+        }
+    }
+}
+
+/// Where a line of play stops in a `GameTree`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TreeNodeKind {
+    Won,
+    Lost,
+    /// Each legal match and the node it leads to, or `None` where the tree
+    /// ran out of nodes before getting there.
+    Choice(Vec<(Match, Option<usize>)>),
+}
+
+/// One position in a `GameTree`: a choice point or the end of a game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeNode {
+    /// The position as `Game::pack` writes it.
+    pub position: Vec<u8>,
+    pub kind: TreeNodeKind,
+}
+
+/// Every line of play from a position, as a graph of the choice points and
+/// endings it reaches. Lines that meet at the same position share its node,
+/// so children are indexes into `nodes` rather than owned subtrees.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameTree {
+    /// The root, where play from the starting position first stops, is first.
+    pub nodes: Vec<TreeNode>,
+}
+impl GameTree {
+    /// Number of won and lost nodes.
+    pub fn leaf_counts(&self) -> (usize, usize) {
+        let count = |k: TreeNodeKind| self.nodes.iter().filter(|n| n.kind == k).count();
+        (count(TreeNodeKind::Won), count(TreeNodeKind::Lost))
+    }
+}
+
+/// Picks uniformly at random among the legal matches.
+pub fn uniform_policy(_: &Game, choices: &[Match], rng: &mut XorShift) -> Match {
+    choices[rng.below(choices.len() as u64) as usize]
+}
+/// Picks at random among the matches made from the greatest distance,
+/// so distance-3 matches win out over distance-1 in the classic game.
+pub fn prefer_far_policy(_: &Game, choices: &[Match], rng: &mut XorShift) -> Match {
+    let far = choices.iter().map(|m| m.1).max().unwrap();
+    let best: Vec<Match> = choices.iter().copied().filter(|m| m.1 == far).collect();
+    best[rng.below(best.len() as u64) as usize]
+}
+
+impl Game {
+    /// Searches every line of play from here as `Search` does, calling
+    /// `on_progress` with the running node count each `PROGRESS_INTERVAL`
+    /// nodes, for a heartbeat during long solves. `None` if `budget` nodes
+    /// weren't enough to finish.
+    pub fn solve_with_progress(
+        &self,
+        budget: usize,
+        mut on_progress: impl FnMut(usize),
+    ) -> Option<SearchOutcome> {
+        let mut search = Search::new(self.clone_for_search());
+        while search.nodes() < budget {
+            let chunk = PROGRESS_INTERVAL.min(budget - search.nodes());
+            if let Some(outcome) = search.step(chunk) {
+                return Some(outcome);
+            }
+            on_progress(search.nodes());
+        }
+        None
+    }
+    /// The fewest piles any line of play from here ends with: the win
+    /// target or below for a winnable game, and how close it gets for one
+    /// that isn't. Searches at most `budget` nodes, skipping positions
+    /// already expanded and lines that can't beat the best so far; if the
+    /// budget runs out this is the best found, which may not be the best,
+    /// or `None` if no line was played out to the end.
+    pub fn best_pile_count(&self, budget: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![self.clone_for_search()];
+        let mut nodes = 0;
+        while let Some(mut g) = to_visit.pop() {
+            if nodes >= budget {
+                break;
+            }
+            nodes += 1;
+            match g.to_next_choice() {
+                Choices::GameWon | Choices::GameLost | Choices::Aborted => {
+                    best = Some(best.map_or(g.tableau.len(), |b| b.min(g.tableau.len())))
+                }
+                Choices::ChooseOne(c) => {
+                    let beaten = best.is_some_and(|b| g.min_piles_lower_bound() >= b);
+                    if beaten || !visited.insert(g.pack()) {
+                        continue;
+                    }
+                    for m in c {
+                        let mut next = g.clone_for_search();
+                        next.make_choice(m);
+                        to_visit.push(next);
+                    }
+                }
+            }
+            if best.is_some_and(|b| b <= self.rules.winning_piles()) {
+                break;
+            }
+        }
+        best
+    }
+    /// Each match open at the first choice point of the classic game dealt
+    /// from `seed`, with what a search of at most `probe_depth` further
+    /// choice points found after making it: `None` if that wasn't enough to
+    /// settle it. Empty if the game never reaches a choice.
+    pub fn first_choice_branches(
+        seed: u64,
+        probe_depth: usize,
+    ) -> Vec<(Match, Option<SearchOutcome>)> {
+        let mut g = Game::from_seed(seed);
+        let choices = match g.play_to_choice() {
+            Choices::ChooseOne(c) => c,
+            Choices::GameWon | Choices::GameLost | Choices::Aborted => return Vec::new(),
+        };
+        choices
+            .into_iter()
+            .map(|m| {
+                let mut branch = g.clone_for_search();
+                branch.reset_choice_points();
+                branch.make_choice(m);
+                let outcome = match play_game_with_budget(branch, probe_depth).result {
+                    Result::AlwaysWin => Some(SearchOutcome::AlwaysWin),
+                    Result::AlwaysLose => Some(SearchOutcome::AlwaysLose),
+                    Result::CanWin => Some(SearchOutcome::CanWin),
+                    Result::GaveUp | Result::Aborted => None,
+                };
+                (m, outcome)
+            })
+            .collect()
+    }
+    /// Whether making `m` here throws away a win: some line of play from
+    /// here wins, but none does after `m`. Each side gets a search of
+    /// `budget` choice points, and `None` means one that mattered gave up
+    /// (or was aborted) before it could tell. `Some(false)` for a match that
+    /// isn't legal here.
+    pub fn is_losing_move(&self, m: Match, budget: usize) -> Option<bool> {
+        if !self.find_matches().contains(&m) {
+            return Some(false);
+        }
+        let play = |mut g: Game| {
+            g.reset_choice_points();
+            play_game_with_budget(g, budget).result
+        };
+        let mut after = self.clone_for_search();
+        after.make_choice(m);
+        let after = play(after);
+        if after.is_winnable() {
+            return Some(false);
+        }
+        match (play(self.clone_for_search()), after) {
+            (Result::AlwaysLose, _) => Some(false),
+            (before, Result::AlwaysLose) if before.is_winnable() => Some(true),
+            _ => None,
+        }
+    }
+    /// How many distinct positions play can reach from here, this one
+    /// included, counting at most `cap`. Every match is tried wherever there
+    /// is one and a card is dealt only when there's none, as in play itself;
+    /// positions are told apart by `pack`, as `Search` does.
+    pub fn reachable_states(&self, cap: usize) -> usize {
+        let mut visited = HashSet::new();
+        let mut to_visit = vec![self.clone_for_search()];
+        while let Some(g) = to_visit.pop() {
+            if visited.len() >= cap {
+                break;
+            }
+            if !visited.insert(g.pack()) {
+                continue;
+            }
+            let matches = g.find_matches();
+            for &m in &matches {
+                let mut next = g.clone_for_search();
+                next.make_choice(m);
+                to_visit.push(next);
+            }
+            if matches.is_empty() {
+                let mut next = g;
+                if next.deal_card().is_some() {
+                    to_visit.push(next);
+                }
+            }
+        }
+        visited.len()
+    }
+    /// The tree of every line of play from here, holding at most
+    /// `max_nodes` nodes. It's built breadth first, so when it's cut short
+    /// the choices nearest the root are the ones filled in.
+    pub fn build_tree(&self, max_nodes: usize) -> GameTree {
+        let mut tree = GameTree::default();
+        let mut index = HashMap::new();
+        let mut to_expand = VecDeque::new();
+        //plays `g` on to its next stop and finds or adds the node there.
+        let mut reach = |mut g: Game, tree: &mut GameTree, to_expand: &mut VecDeque<_>| {
+            let choices = g.play_to_choice();
+            let position = g.pack();
+            if let Some(&ix) = index.get(&position) {
+                return Some(ix);
+            }
+            if tree.nodes.len() >= max_nodes {
+                return None;
+            }
+            let ix = tree.nodes.len();
+            let kind = match choices {
+                Choices::GameWon => TreeNodeKind::Won,
+                Choices::GameLost | Choices::Aborted => TreeNodeKind::Lost,
+                Choices::ChooseOne(c) => {
+                    to_expand.push_back((ix, g));
+                    TreeNodeKind::Choice(c.into_iter().map(|m| (m, None)).collect())
+                }
+            };
+            index.insert(position.clone(), ix);
+            tree.nodes.push(TreeNode { position, kind });
+            Some(ix)
+        };
+        reach(self.clone_for_search(), &mut tree, &mut to_expand);
+        while let Some((ix, g)) = to_expand.pop_front() {
+            let matches: Vec<Match> = match &tree.nodes[ix].kind {
+                TreeNodeKind::Choice(c) => c.iter().map(|&(m, _)| m).collect(),
+                _ => unreachable!("only choice points are expanded"),
+            };
+            for (i, m) in matches.into_iter().enumerate() {
+                let mut next = g.clone_for_search();
+                next.make_choice(m);
+                let child = reach(next, &mut tree, &mut to_expand);
+                if let TreeNodeKind::Choice(c) = &mut tree.nodes[ix].kind {
+                    c[i].1 = child;
+                }
+            }
+        }
+        tree
+    }
+    /// Estimates how often this position is won by playing `trials` games
+    /// to the end, picking uniformly at random at each choice. The rollouts
+    /// draw from this crate's `XorShift`, so the same `seed` gives the same
+    /// estimate whatever `rand` release it's built with.
+    pub fn monte_carlo_winnable(&self, trials: usize, seed: u64) -> f64 {
+        self.monte_carlo_policy(trials, seed, uniform_policy)
+    }
+    /// Like `monte_carlo_winnable`, but `policy` picks the match at each
+    /// choice. Comparing policies shows how much strategy matters here.
+    pub fn monte_carlo_policy<P>(&self, trials: usize, seed: u64, mut policy: P) -> f64
+    where
+        P: FnMut(&Game, &[Match], &mut XorShift) -> Match,
+    {
+        if trials == 0 {
+            return 0.0;
+        }
+        let mut rng = XorShift::new(seed);
+        let mut wins = 0;
+        for _ in 0..trials {
+            let mut g = self.clone_for_search();
+            loop {
+                match g.to_next_choice() {
+                    Choices::GameWon => {
+                        wins += 1;
+                        break;
+                    }
+                    Choices::GameLost | Choices::Aborted => break,
+                    Choices::ChooseOne(c) => {
+                        let m = policy(&g, &c, &mut rng);
+                        g.make_choice(m);
+                    }
+                }
+            }
+        }
+        wins as f64 / trials as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::game;
+    use crate::{play_game, play_game_with_budget, DEFAULT_BUDGET};
+
+    #[test]
+    fn stepping_reaches_the_same_outcome_as_run() {
+        for seed in [235, 1392, 0] {
+            let whole = Search::new(Game::from_seed(seed)).run();
+            let mut search = Search::new(Game::from_seed(seed));
+            let mut calls = 0;
+            let stepped = loop {
+                calls += 1;
+                if let Some(o) = search.step(7) {
+                    break o;
+                }
+            };
+            assert_eq!(stepped, whole, "seed {}", seed);
+            assert!(calls > 1);
+            assert_eq!(search.step(7), Some(whole));
+        }
+    }
+
+    //AC 2D 4C 2C 3C 3D: every line of this wins, though 2C can go onto 4C or AC.
+    const FORCED_WIN: &[u8] = &[0, 14, 3, 1, 2, 15];
+
+    #[test]
+    fn monte_carlo_wins_every_rollout_of_a_forced_win() {
+        let g = game(FORCED_WIN);
+        assert_eq!(
+            play_game_with_budget(g.clone(), DEFAULT_BUDGET).choice_points,
+            1
+        );
+        assert_eq!(g.monte_carlo_winnable(20, 1), 1.0);
+        //and the same seed gives the same estimate.
+        let g = Game::from_seed(235);
+        assert_eq!(g.monte_carlo_winnable(20, 9), g.monte_carlo_winnable(20, 9));
+    }
+
+    #[test]
+    fn every_policy_wins_a_forced_win() {
+        let g = game(FORCED_WIN);
+        assert_eq!(g.monte_carlo_policy(20, 1, uniform_policy), 1.0);
+        assert_eq!(g.monte_carlo_policy(20, 1, prefer_far_policy), 1.0);
+        //or one that always takes the last match offered.
+        assert_eq!(g.monte_carlo_policy(20, 1, |_, c, _| c[c.len() - 1]), 1.0);
+    }
+
+    #[test]
+    fn reachable_states_counts_each_position_once() {
+        //empty, then each of four deals, then one position per choice.
+        //AC 2D 3C 4C.
+        let g = game(&[0, 14, 2, 3]);
+        assert_eq!(g.reachable_states(100), 7);
+        assert_eq!(g.reachable_states(3), 3);
+        //AC 2C.
+        assert_eq!(game(&[0, 1]).reachable_states(100), 4);
+    }
+
+    #[test]
+    fn tree_has_a_node_per_stop_and_classifies_the_leaves() {
+        //AC 2D 3C 4C.
+        let g = game(&[0, 14, 2, 3]);
+        let tree = g.build_tree(100);
+        assert_eq!(tree.nodes.len(), 3);
+        assert_eq!(tree.leaf_counts(), (0, 2));
+        match &tree.nodes[0].kind {
+            TreeNodeKind::Choice(children) => {
+                assert_eq!(children.len(), 2);
+                assert!(children.iter().all(|(_, child)| child.is_some()));
+            }
+            other => panic!("the root should be a choice, got {:?}", other),
+        }
+        let cut = g.build_tree(1);
+        assert_eq!(cut.nodes.len(), 1);
+        assert!(
+            matches!(&cut.nodes[0].kind, TreeNodeKind::Choice(c) if c.iter().all(|(_, child)| child.is_none()))
+        );
+
+        let (wins, losses) = game(FORCED_WIN)
+            .build_tree(100)
+            .leaf_counts();
+        assert!(wins > 0);
+        assert_eq!(losses, 0);
+    }
+
+    #[test]
+    fn best_pile_count_finds_the_closest_an_unwinnable_game_gets() {
+        //4C onto AC lets 3D take 3C and then 2D; 4C onto 3C strands 3D.
+        //AC 2D 3C 4C 3D.
+        let g = game(&[0, 14, 2, 3, 15]);
+        assert_eq!(play_game(g.clone()).result, Result::AlwaysLose);
+        assert_eq!(g.best_pile_count(1000), Some(2));
+        assert_eq!(g.best_pile_count(0), None);
+        assert_eq!(game(FORCED_WIN).best_pile_count(1000), Some(1));
+    }
+
+    #[test]
+    fn first_choice_branches_probe_each_opening_match() {
+        let mut g = Game::from_seed(1392);
+        let first = match g.play_to_choice() {
+            Choices::ChooseOne(c) => c,
+            other => panic!("seed 1392 should reach a choice, got {:?}", other),
+        };
+        let branches = Game::first_choice_branches(1392, DEFAULT_BUDGET);
+        assert_eq!(branches.len(), 2);
+        assert_eq!(branches.iter().map(|b| b.0).collect::<Vec<_>>(), first);
+        assert!(branches.iter().all(|b| b.1 == Some(SearchOutcome::CanWin)));
+        //too shallow a probe settles nothing.
+        let shallow = Game::first_choice_branches(1392, 0);
+        assert!(shallow.iter().all(|b| b.1.is_none()));
+    }
+
+    #[test]
+    fn only_the_move_that_throws_the_win_away_is_losing() {
+        //2C onto 3C can still win; every line after 2C onto AC loses.
+        //AC 2D 3C 2C 4C.
+        let mut g = game(&[0, 14, 2, 1, 3]);
+        assert!(matches!(g.play_to_choice(), Choices::ChooseOne(c) if c == [(3, 1), (3, 3)]));
+        assert_eq!(g.is_losing_move((3, 1), DEFAULT_BUDGET), Some(false));
+        assert_eq!(g.is_losing_move((3, 3), DEFAULT_BUDGET), Some(true));
+        assert_eq!(g.is_losing_move((2, 1), DEFAULT_BUDGET), Some(false));
+        //with no budget the search can't tell that 2C onto AC loses.
+        assert_eq!(g.is_losing_move((3, 3), 0), None);
+    }
+
+    #[test]
+    fn progress_is_reported_while_a_branchy_seed_is_solved() {
+        //seed 10 takes a little over 12,000 nodes to settle.
+        let mut counts = Vec::new();
+        let outcome = Game::from_seed(10).solve_with_progress(usize::MAX, |n| counts.push(n));
+        assert_eq!(outcome, Some(Search::new(Game::from_seed(10)).run()));
+        assert!(!counts.is_empty());
+        for (i, &n) in counts.iter().enumerate() {
+            assert_eq!(n, (i + 1) * PROGRESS_INTERVAL);
+        }
+        //and a budget it can't finish in gives up after its last report.
+        let mut reports = 0;
+        let outcome = Game::from_seed(10).solve_with_progress(PROGRESS_INTERVAL, |_| reports += 1);
+        assert_eq!((outcome, reports), (None, 1));
+    }
+}
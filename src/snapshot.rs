@@ -0,0 +1,450 @@
+use crate::{
+    Card, Deck, Game, MatchDistance, PlacedCard, RemovalMode, Rules, SavedGame, SophieError,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// Format version written into every serialized `SavedGame`. Bump it whenever
+/// the layout changes, and have `SavedGame::from_bytes` migrate or reject the
+/// older versions.
+pub const SAVE_VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The snapshot was written by a format version this build can't read.
+    UnsupportedVersion(u16),
+    Malformed(serde_json::Error),
+    /// Bytes handed to `Game::unpack` don't describe a position.
+    BadPacking,
+    /// A whole-game snapshot parsed, but its deck and tableau don't agree.
+    Inconsistent(&'static str),
+}
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(v) => write!(
+                f,
+                "saved game has format version {}, but only version {} is supported",
+                v, SAVE_VERSION
+            ),
+            SnapshotError::Malformed(e) => write!(f, "saved game is malformed: {}", e),
+            SnapshotError::BadPacking => write!(f, "packed position is invalid"),
+            SnapshotError::Inconsistent(why) => write!(f, "saved game is inconsistent: {}", why),
+        }
+    }
+}
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Malformed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+//Just enough of a snapshot to decide how to read the rest of it.
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u16,
+}
+
+impl SavedGame {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("SavedGame always serializes")
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_json().into_bytes()
+    }
+    pub fn from_json(s: &str) -> Result<Self, SophieError> {
+        Self::from_bytes(s.as_bytes())
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SophieError> {
+        let probe: VersionProbe = serde_json::from_slice(bytes)?;
+        if probe.version != SAVE_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(probe.version).into());
+        }
+        let saved: SavedGame = serde_json::from_slice(bytes)?;
+        let bad = |why| SophieError::from(SnapshotError::Inconsistent(why));
+        if saved.tableau.iter().any(|p| p.card.0 >= 54) {
+            return Err(bad("a pile has a card that isn't in any deck"));
+        }
+        if saved.tableau.iter().any(|p| p.size == 0) {
+            return Err(bad("tableau has an empty pile"));
+        }
+        Ok(saved)
+    }
+}
+
+//The checks a snapshot's piles must pass against the deck they were dealt
+//from, whether it came with the snapshot or is the game restoring it.
+fn check_piles(
+    deck: &[Card],
+    pos: usize,
+    tableau: &[PlacedCard],
+    removal: RemovalMode,
+) -> Result<(), SophieError> {
+    let bad = |why| SophieError::from(SnapshotError::Inconsistent(why));
+    if pos > deck.len() {
+        return Err(bad("deck position is past the end of the deck"));
+    }
+    if tableau.iter().any(|p| p.size == 0) {
+        return Err(bad("tableau has an empty pile"));
+    }
+    //discarded cards leave the piles two at a time.
+    let held = tableau.iter().map(|p| p.size).sum::<usize>();
+    let consistent = match removal {
+        RemovalMode::Merge => held == pos,
+        RemovalMode::Discard => held <= pos && (pos - held).is_multiple_of(2),
+    };
+    if !consistent {
+        return Err(bad("piles don't hold exactly the dealt cards"));
+    }
+    let dealt = &deck[..pos];
+    if tableau.iter().any(|p| !dealt.contains(&p.card)) {
+        return Err(bad("a pile has an undealt card on top"));
+    }
+    let mut tops: Vec<Card> = tableau.iter().map(|p| p.card).collect();
+    tops.sort();
+    tops.dedup();
+    if tops.len() != tableau.len() {
+        return Err(bad("two piles have the same card on top"));
+    }
+    Ok(())
+}
+
+//A whole game, deck and rules included, so it can be picked up without the
+//`Game` it came from.
+#[derive(Serialize, Deserialize)]
+struct GameSnapshot {
+    version: u16,
+    deck: Vec<Card>,
+    pos: usize,
+    distances: [MatchDistance; 2],
+    //the deck is stored in deal order either way; this only keeps the rules.
+    #[serde(default)]
+    deal_from_bottom: bool,
+    #[serde(default = "crate::one")]
+    win_target: usize,
+    #[serde(default)]
+    max_tableau_abort: Option<usize>,
+    #[serde(default)]
+    adjacent_ranks: bool,
+    #[serde(default)]
+    ace_high: bool,
+    #[serde(default)]
+    removal: RemovalMode,
+    #[serde(default)]
+    seed: Option<u64>,
+    tableau: Vec<PlacedCard>,
+}
+
+impl Game {
+    /// The whole game as JSON, for `Game::from_json` to pick up elsewhere.
+    /// Unlike a `SavedGame` this carries the deck, but not the move log.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&GameSnapshot {
+            version: SAVE_VERSION,
+            deck: self.deck.list.to_vec(),
+            pos: self.deck.pos,
+            distances: self.rules.distances,
+            deal_from_bottom: self.rules.deal_from_bottom,
+            win_target: self.rules.win_target,
+            max_tableau_abort: self.rules.max_tableau_abort,
+            adjacent_ranks: self.rules.adjacent_ranks,
+            ace_high: self.rules.ace_high,
+            removal: self.rules.removal,
+            seed: self.seed,
+            tableau: self.tableau.clone(),
+        })
+        .expect("GameSnapshot always serializes")
+    }
+    /// Reads a game written by `Game::to_json`. The deck must be all 52 cards
+    /// once each, perhaps with both jokers, and the piles must hold exactly
+    /// the dealt cards (less pairs discarded, under `RemovalMode::Discard`)
+    /// with a dealt card on top of each; match flags are recomputed, not
+    /// trusted.
+    pub fn from_json(s: &str) -> Result<Game, SophieError> {
+        let probe: VersionProbe = serde_json::from_str(s)?;
+        if probe.version != SAVE_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(probe.version).into());
+        }
+        let snap: GameSnapshot = serde_json::from_str(s)?;
+        let bad = |why| SophieError::from(SnapshotError::Inconsistent(why));
+        //the 52 cards, then the two jokers.
+        let mut seen = [false; 54];
+        for c in &snap.deck {
+            match seen.get_mut(usize::from(c.0)) {
+                Some(s) if !*s => *s = true,
+                _ => return Err(bad("deck must hold each card once")),
+            }
+        }
+        if !seen[..52].iter().all(|&s| s) || seen[52] != seen[53] {
+            return Err(bad(
+                "deck must hold all 52 cards, and both jokers or neither",
+            ));
+        }
+        check_piles(&snap.deck, snap.pos, &snap.tableau, snap.removal)?;
+        let [a, b] = snap.distances;
+        if a == 0 || b == 0 || a == b {
+            return Err(bad("match distances must be distinct and positive"));
+        }
+        let deck = Deck {
+            list: snap.deck.into(),
+            pos: snap.pos,
+        };
+        let mut g = Game::from_deck(
+            deck,
+            Rules {
+                distances: snap.distances,
+                deal_from_bottom: snap.deal_from_bottom,
+                win_target: snap.win_target,
+                max_tableau_abort: snap.max_tableau_abort,
+                adjacent_ranks: snap.adjacent_ranks,
+                ace_high: snap.ace_high,
+                removal: snap.removal,
+                ..Rules::default()
+            },
+        );
+        g.seed = snap.seed;
+        g.tableau = snap.tableau;
+        g.recompute_all_matches();
+        Ok(g)
+    }
+    /// Restores a snapshot written by `SavedGame::to_bytes`. The piles must
+    /// hold exactly the cards this game's deck had dealt by the snapshot's
+    /// position, as `Game::from_json` checks, or the game is left untouched.
+    /// The stored match flags aren't trusted; they're recomputed under this
+    /// game's rules.
+    pub fn restore_bytes(&mut self, bytes: &[u8]) -> Result<(), SophieError> {
+        let saved = SavedGame::from_bytes(bytes)?;
+        check_piles(
+            &self.deck.list,
+            saved.pos,
+            &saved.tableau,
+            self.rules.removal,
+        )?;
+        self.restore(saved);
+        self.recompute_all_matches();
+        Ok(())
+    }
+}
+
+impl Game {
+    /// A compact key for the position: the deck position, then the undealt
+    /// cards in deal order, then the top card of each pile, one byte each.
+    /// Equal positions pack to equal bytes, so this is cheap to hash and
+    /// compare when deduplicating.
+    pub fn pack(&self) -> Vec<u8> {
+        let undealt = self.deck.undealt();
+        let mut bytes = Vec::with_capacity(1 + undealt.len() + self.tableau.len());
+        bytes.push(self.deck.pos as u8);
+        bytes.extend(undealt.iter().map(|c| c.0));
+        bytes.extend(self.tableau.iter().map(|p| p.top().0));
+        bytes
+    }
+    /// A hash of the whole game: the deck, how far it's dealt, every pile
+    /// with its size and match flags, and the choice points met. Unlike
+    /// `pack` this sees the flags and counters, so a change to how play
+    /// reaches a position shows up here even when the position doesn't.
+    /// It's FNV-1a, so the value is the same on every build and platform.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut eat = |bytes: &[u8]| {
+            for &b in bytes {
+                hash ^= u64::from(b);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for c in self.deck.list.iter() {
+            eat(&[c.0]);
+        }
+        eat(&(self.deck.pos as u64).to_le_bytes());
+        for p in &self.tableau {
+            eat(&[p.card.0, p.matches_one as u8, p.matches_three as u8]);
+            eat(&(p.size as u64).to_le_bytes());
+        }
+        eat(&(self.choice_points as u64).to_le_bytes());
+        hash
+    }
+    /// The deck position and the top card of each pile, one byte each:
+    /// `pack` without the undealt cards. Within one deal the position fixes
+    /// the undealt cards, so this tells positions apart just as `pack` does;
+    /// across deals it lumps together boards that look alike but play out
+    /// differently. It's for grouping positions in statistics and
+    /// heuristics only; a transposition table keyed on it is unsound.
+    pub fn match_signature(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + self.tableau.len());
+        bytes.push(self.deck.pos as u8);
+        bytes.extend(self.tableau.iter().map(|p| p.top().0));
+        bytes
+    }
+    /// Rebuilds a game from `pack`'s bytes under the classic rules, failing
+    /// with `SnapshotError::BadPacking` if they don't describe a position.
+    /// Which cards lie buried in which pile isn't packed, so each pile comes
+    /// back as a single card; none of that affects play. Only the standard
+    /// deck unpacks, so positions with jokers fail.
+    pub fn unpack(bytes: &[u8]) -> Result<Game, SophieError> {
+        let bad = || SophieError::from(SnapshotError::BadPacking);
+        let full = Deck::new_unshuffled().list;
+        let (&pos, rest) = bytes.split_first().ok_or_else(bad)?;
+        let pos = usize::from(pos);
+        let undealt_len = full.len().checked_sub(pos).ok_or_else(bad)?;
+        if rest.len() < undealt_len {
+            return Err(bad());
+        }
+        let (undealt, tops) = rest.split_at(undealt_len);
+        if tops.len() > pos || (pos > 0 && tops.is_empty()) {
+            return Err(bad());
+        }
+        let mut seen = vec![false; full.len()];
+        for &b in rest {
+            if seen.get(usize::from(b)) != Some(&false) {
+                return Err(bad());
+            }
+            seen[usize::from(b)] = true;
+        }
+        //the dealt part of the deck: the pile tops, then whatever is buried.
+        let mut list: Vec<Card> = tops.iter().map(|&b| Card(b)).collect();
+        list.extend(full.iter().filter(|c| !seen[usize::from(c.0)]));
+        list.extend(undealt.iter().map(|&b| Card(b)));
+        let deck = Deck {
+            list: list.into(),
+            pos,
+        };
+        let mut g = Game::from_deck(deck, Rules::default());
+        g.tableau = tops
+            .iter()
+            .map(|&b| PlacedCard {
+                card: Card(b),
+                size: 1,
+                matches_one: false,
+                matches_three: false,
+            })
+            .collect();
+        g.recompute_all_matches();
+        Ok(g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::game;
+
+    fn mid_game() -> Game {
+        let mut g = Game::from_seed(1);
+        for _ in 0..10 {
+            g.deal_card();
+        }
+        g
+    }
+
+    #[test]
+    fn saved_game_round_trips_through_bytes() {
+        let mut g = mid_game();
+        let before = format!("{:?}", g);
+        let bytes = g.save_game().to_bytes();
+        for _ in 0..5 {
+            g.deal_card();
+        }
+        g.restore(SavedGame::from_bytes(&bytes).unwrap());
+        assert_eq!(format!("{:?}", g), before);
+    }
+
+    #[test]
+    fn saved_game_with_a_bumped_version_is_rejected() {
+        let json = mid_game().save_game().to_json();
+        let current = format!("\"version\":{}", SAVE_VERSION);
+        assert!(json.contains(&current));
+        let bumped = json.replace(&current, &format!("\"version\":{}", SAVE_VERSION + 1));
+        match SavedGame::from_json(&bumped) {
+            Err(SophieError::BadSnapshot(SnapshotError::UnsupportedVersion(v))) => {
+                assert_eq!(v, SAVE_VERSION + 1)
+            }
+            other => panic!(
+                "expected an unsupported version, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+
+    #[test]
+    fn restore_bytes_rejects_piles_the_deck_didnt_deal() {
+        let bytes = mid_game().save_game().to_bytes();
+        let json = String::from_utf8(bytes.clone()).unwrap();
+        let rejects = |g: &mut Game, bytes: &[u8]| {
+            let before = format!("{:?}", g);
+            match g.restore_bytes(bytes) {
+                Err(SophieError::BadSnapshot(SnapshotError::Inconsistent(_))) => {}
+                other => panic!("expected an inconsistency, got {:?}", other),
+            }
+            assert_eq!(format!("{:?}", g), before);
+        };
+        //seed 2 deals different cards, so seed 1's piles aren't in it.
+        rejects(&mut Game::from_seed(2), &bytes);
+        let mut g = Game::from_seed(1);
+        g.restore_bytes(&bytes).unwrap();
+        let with = |from: &str, to: &str| {
+            assert!(json.contains(from));
+            json.replacen(from, to, 1)
+        };
+        rejects(&mut g, with("\"pos\":10", "\"pos\":60").as_bytes());
+        rejects(&mut g, with("\"pos\":10", "\"pos\":11").as_bytes());
+        rejects(&mut g, with("\"size\":1", "\"size\":0").as_bytes());
+        let card = format!("\"card\":{}", g.tableau()[0].top().0);
+        rejects(&mut g, with(&card, "\"card\":60").as_bytes());
+    }
+
+    #[test]
+    fn pack_round_trips_through_unpack() {
+        let g = mid_game();
+        let unpacked = Game::unpack(&g.pack()).unwrap();
+        assert_eq!(unpacked.pack(), g.pack());
+        assert_eq!(unpacked.tableau_cards(), g.tableau_cards());
+        assert_eq!(unpacked.find_matches(), g.find_matches());
+    }
+
+    #[test]
+    fn equal_positions_pack_to_equal_bytes() {
+        assert_eq!(mid_game().pack(), mid_game().pack());
+        let mut further = mid_game();
+        further.deal_card();
+        assert_ne!(further.pack(), mid_game().pack());
+    }
+
+    #[test]
+    fn buried_cards_dont_change_the_match_signature() {
+        let played = |cards: &[u8]| {
+            let mut g = game(cards);
+            g.play_to_choice();
+            g
+        };
+        //2C lands on AC in one and on KC in the other.
+        //AC 2C 9H and KC 2C 9H.
+        let a = played(&[0, 1, 34]);
+        let b = played(&[12, 1, 34]);
+        assert_eq!(a.tableau_cards(), b.tableau_cards());
+        assert_eq!(a.match_signature(), b.match_signature());
+        //AC 2C 9S.
+        assert_ne!(a.match_signature(), played(&[0, 1, 47]).match_signature());
+    }
+
+    #[test]
+    fn seed_one_has_a_fixed_fingerprint_after_five_deals() {
+        let dealt = |n, check| {
+            let mut g = Game::from_seed(1);
+            g.set_check_on_deal(check);
+            for _ in 0..n {
+                g.deal_card();
+            }
+            g
+        };
+        let g = dealt(5, true);
+        //JS 3H AC 2H 6D.
+        assert_eq!(g.tableau_cards(), [Card(49), Card(28), Card(0), Card(27), Card(18)]);
+        assert_eq!(g.fingerprint(), 10_286_584_223_299_594_549);
+        //8C matches AC, so one more deal sets a flag that `pack` ignores.
+        let (flagged, unflagged) = (dealt(6, true), dealt(6, false));
+        assert_eq!(flagged.pack(), unflagged.pack());
+        assert_ne!(flagged.fingerprint(), unflagged.fingerprint());
+    }
+}
@@ -0,0 +1,680 @@
+use crate::{
+    play_game, play_game_with_budget, search_game, Game, GameReport, MatchFrequency, Result, Rules,
+    Search, SearchOutcome, DEFAULT_BUDGET,
+};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Width of the `max_tableau_len` ranges `SweepStats::by_max_len` groups by.
+pub const MAX_LEN_BUCKET: usize = 5;
+
+/// Most games `sweep_parallel` has started but not yet reported.
+pub const REORDER_WINDOW: usize = 256;
+
+/// Totals over a run of many games.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SweepStats {
+    pub games: usize,
+    pub always_win: usize,
+    pub always_lose: usize,
+    pub can_win: usize,
+    pub gave_up: usize,
+    /// Games cut short by the rules' `max_tableau_abort`.
+    pub aborted: usize,
+    pub near_misses: usize,
+    /// Number of lost games, keyed by the fewest piles they could be reduced to.
+    pub lost_piles: BTreeMap<usize, usize>,
+    /// Games and winnable games, keyed by the start of the `MAX_LEN_BUCKET`
+    /// wide range their `max_tableau_len` falls in.
+    pub by_max_len: BTreeMap<usize, (usize, usize)>,
+    /// The matches made along every winning line of every game.
+    pub winning_matches: MatchFrequency,
+    /// Number of games with a winning line found, keyed by the moves in the
+    /// shortest one.
+    pub win_lengths: BTreeMap<usize, usize>,
+    /// Games and winnable games, indexed by the rank of the first card dealt.
+    /// Games opening on a joker aren't counted here or in `by_first_suit`.
+    pub by_first_rank: [(usize, usize); 13],
+    /// Games and winnable games, indexed by the suit of the first card dealt.
+    pub by_first_suit: [(usize, usize); 4],
+    //sum of per-game branching factors, over the games that had choice points.
+    branching_total: f64,
+    branching_games: usize,
+}
+impl SweepStats {
+    pub fn add(&mut self, report: &GameReport) {
+        self.games += 1;
+        match report.result {
+            Result::AlwaysWin => {
+                self.always_win += 1;
+            }
+            Result::AlwaysLose => {
+                self.always_lose += 1;
+                if let Some(piles) = report.final_pile_count {
+                    *self.lost_piles.entry(piles).or_default() += 1;
+                }
+            }
+            Result::CanWin => {
+                self.can_win += 1;
+            }
+            Result::GaveUp => {
+                self.gave_up += 1;
+            }
+            Result::Aborted => {
+                self.aborted += 1;
+            }
+        }
+        if report.near_miss {
+            self.near_misses += 1;
+        }
+        self.winning_matches.merge(&report.winning_matches);
+        if let Some(len) = report.shortest_win {
+            *self.win_lengths.entry(len).or_default() += 1;
+        }
+        let bucket = report.max_tableau_len / MAX_LEN_BUCKET * MAX_LEN_BUCKET;
+        let (games, wins) = self.by_max_len.entry(bucket).or_default();
+        *games += 1;
+        if report.result.is_winnable() {
+            *wins += 1;
+        }
+        if let Some(card) = report.first_card.filter(|c| !c.is_wild()) {
+            let winnable = usize::from(report.result.is_winnable());
+            for (games, wins) in [
+                &mut self.by_first_rank[card.rank_index()],
+                &mut self.by_first_suit[card.suit_index()],
+            ] {
+                *games += 1;
+                *wins += winnable;
+            }
+        }
+        if report.choice_points > 0 {
+            self.branching_total += report.branching_factor;
+            self.branching_games += 1;
+        }
+    }
+    /// Mean of the per-game branching factors, ignoring games that never
+    /// reached a choice.
+    pub fn mean_branching_factor(&self) -> f64 {
+        if self.branching_games == 0 {
+            0.0
+        } else {
+            self.branching_total / self.branching_games as f64
+        }
+    }
+    /// (bucket start, games, fraction winnable) for each `by_max_len` bucket,
+    /// narrowest tableaus first.
+    pub fn win_rate_by_max_len(&self) -> Vec<(usize, usize, f64)> {
+        self.by_max_len
+            .iter()
+            .map(|(&bucket, &(games, wins))| (bucket, games, wins as f64 / games as f64))
+            .collect()
+    }
+    /// (games, fraction winnable) for each first-card rank, Ace to King.
+    /// Ranks no game opened on have a rate of 0.
+    pub fn win_rate_by_first_rank(&self) -> Vec<(usize, f64)> {
+        self.by_first_rank.iter().map(|&b| bucket_rate(b)).collect()
+    }
+    /// (games, fraction winnable) for each first-card suit, in `Suit` order.
+    pub fn win_rate_by_first_suit(&self) -> Vec<(usize, f64)> {
+        self.by_first_suit.iter().map(|&b| bucket_rate(b)).collect()
+    }
+    /// Fraction of games with at least one winning line.
+    pub fn win_rate(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            (self.always_win + self.can_win) as f64 / self.games as f64
+        }
+    }
+}
+
+fn bucket_rate((games, wins): (usize, usize)) -> (usize, f64) {
+    if games == 0 {
+        (0, 0.0)
+    } else {
+        (games, wins as f64 / games as f64)
+    }
+}
+
+/// Plays every seed in `seeds` under `rules`.
+pub fn sweep(seeds: Range<u64>, rules: &Rules) -> SweepStats {
+    sweep_with(seeds, rules, DEFAULT_BUDGET, |_| {})
+}
+
+/// Plays every seed in `seeds` under `rules` with a search budget of
+/// `budget` choice points, handing each report to `on_game` as it finishes.
+pub fn sweep_with<F: FnMut(&GameReport)>(
+    seeds: Range<u64>,
+    rules: &Rules,
+    budget: usize,
+    on_game: F,
+) -> SweepStats {
+    sweep_until(seeds, rules, budget, &AtomicBool::new(false), on_game)
+}
+
+/// `sweep_with` that checks `stop` before each game and, once it's set,
+/// returns the totals so far instead of playing on. Setting it from a signal
+/// handler lets a long run be cut short without losing what it found.
+pub fn sweep_until<F: FnMut(&GameReport)>(
+    seeds: Range<u64>,
+    rules: &Rules,
+    budget: usize,
+    stop: &AtomicBool,
+    mut on_game: F,
+) -> SweepStats {
+    let mut stats = SweepStats::default();
+    for seed in seeds {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let report = play_game_with_budget(Game::from_seed_with_rules(seed, rules.clone()), budget);
+        on_game(&report);
+        stats.add(&report);
+    }
+    stats
+}
+
+/// `sweep_with` spread over `threads` threads. Games finish out of order,
+/// but each report is held back until every earlier seed's has gone to
+/// `on_game`, so it sees them in seed order just as `sweep_with` would. No
+/// more than `REORDER_WINDOW` games are in flight or waiting at once.
+/// Setting `stop` ends it as `sweep_until` does: games already being played
+/// are finished, and the totals cover every seed before the first one that
+/// wasn't. If a game panics, the other workers finish what they're playing
+/// and the panic is raised again here.
+pub fn sweep_parallel<F: FnMut(&GameReport)>(
+    seeds: Range<u64>,
+    rules: &Rules,
+    budget: usize,
+    threads: usize,
+    stop: &AtomicBool,
+    on_game: F,
+) -> SweepStats {
+    sweep_parallel_by(
+        seeds,
+        threads,
+        stop,
+        |seed| play_game_with_budget(Game::from_seed_with_rules(seed, rules.clone()), budget),
+        on_game,
+    )
+}
+
+//Tells the sweep when a worker panics, as the seed it was playing would
+//otherwise be waited on forever.
+struct PanicGuard<'a> {
+    failed: &'a AtomicBool,
+    report_tx: mpsc::Sender<Option<(u64, GameReport)>>,
+}
+impl Drop for PanicGuard<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.failed.store(true, Ordering::Relaxed);
+            let _ = self.report_tx.send(None);
+        }
+    }
+}
+
+//`sweep_parallel` with the game playing passed in.
+fn sweep_parallel_by<F: FnMut(&GameReport)>(
+    seeds: Range<u64>,
+    threads: usize,
+    stop: &AtomicBool,
+    play: impl Fn(u64) -> GameReport + Sync,
+    mut on_game: F,
+) -> SweepStats {
+    let threads = threads.max(1);
+    let window = REORDER_WINDOW.max(threads);
+    let mut stats = SweepStats::default();
+    let failed = AtomicBool::new(false);
+    let quitting = || stop.load(Ordering::Relaxed) || failed.load(Ordering::Relaxed);
+    let (job_tx, job_rx) = mpsc::channel::<u64>();
+    //`None` is a worker's last word as it panics.
+    let (report_tx, report_rx) = mpsc::channel::<Option<(u64, GameReport)>>();
+    let job_rx = Mutex::new(job_rx);
+    thread::scope(|scope| {
+        let mut workers = Vec::with_capacity(threads);
+        for _ in 0..threads {
+            let guard = PanicGuard {
+                failed: &failed,
+                report_tx: report_tx.clone(),
+            };
+            let (job_rx, play, quitting) = (&job_rx, &play, &quitting);
+            workers.push(scope.spawn(move || loop {
+                if quitting() {
+                    return;
+                }
+                //the lock is dropped before playing, so workers only queue here.
+                let seed = match job_rx.lock().unwrap().recv() {
+                    Ok(seed) => seed,
+                    Err(_) => return,
+                };
+                if guard.report_tx.send(Some((seed, play(seed)))).is_err() {
+                    return;
+                }
+            }));
+        }
+        drop(report_tx);
+        let mut next_job = seeds.start;
+        let mut waiting = BTreeMap::new();
+        'seeds: for next_out in seeds.clone() {
+            if quitting() {
+                break;
+            }
+            while next_job < seeds.end && next_job < next_out + window as u64 {
+                job_tx.send(next_job).expect("workers outlive the jobs");
+                next_job += 1;
+            }
+            while !waiting.contains_key(&next_out) {
+                //every worker has quit, which they only do once stopped, or
+                //one of them has panicked.
+                let Ok(Some((seed, report))) = report_rx.recv() else {
+                    break 'seeds;
+                };
+                waiting.insert(seed, report);
+            }
+            let report = waiting.remove(&next_out).unwrap();
+            on_game(&report);
+            stats.add(&report);
+        }
+        drop(job_tx);
+        //joined here rather than by the scope, which would raise a panic of
+        //its own in place of the worker's.
+        for worker in workers {
+            if let Err(panic) = worker.join() {
+                panic::resume_unwind(panic);
+            }
+        }
+    });
+    stats
+}
+
+/// Time a sweep spent in each phase of play, summed over every game.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Timings {
+    /// Shuffling and setting up each game.
+    pub shuffling: Duration,
+    pub dealing: Duration,
+    /// Making forced matches; chosen matches count as `choices`.
+    pub matching: Duration,
+    /// Finding the choices, and saving, restoring and following branches.
+    pub choices: Duration,
+}
+
+/// `sweep_with`, also timing where the search spends its effort. Timing
+/// every step slows the sweep, so only use this when that's the point.
+pub fn sweep_timed<F: FnMut(&GameReport)>(
+    seeds: Range<u64>,
+    rules: &Rules,
+    budget: usize,
+    mut on_game: F,
+) -> (SweepStats, Timings) {
+    let mut stats = SweepStats::default();
+    let mut timings = Timings::default();
+    for seed in seeds {
+        let start = Instant::now();
+        let mut g = Game::from_seed_with_rules(seed, rules.clone());
+        timings.shuffling += start.elapsed();
+        let report = search_game(&mut g, budget, Some(&mut timings));
+        on_game(&report);
+        stats.add(&report);
+    }
+    (stats, timings)
+}
+
+/// The longest run of consecutive reports that are forced wins.
+pub fn longest_forced_win_streak(reports: &[GameReport]) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for r in reports {
+        if r.is_forced_win() {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+/// Two rule variants played over the same shuffles.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Comparison {
+    pub a: SweepStats,
+    pub b: SweepStats,
+    /// Seeds winnable under `a` but not `b`. Seeds where either search gave
+    /// up or aborted aren't counted here or in `b_only`.
+    pub a_only: usize,
+    pub b_only: usize,
+}
+
+impl Comparison {
+    /// How likely a split of the seeds only one variant wins at least this
+    /// lopsided would be if the variants were equally good; see
+    /// `sign_test_p_value`. Small values mean the win rates really differ.
+    pub fn p_value(&self) -> f64 {
+        sign_test_p_value(self.a_only, self.b_only)
+    }
+}
+
+/// Two-sided exact sign test (McNemar's exact test) on the discordant pairs
+/// of a paired comparison: `a_only` seeds won only under A and `b_only` only
+/// under B. Under the hypothesis that neither variant is better, each
+/// discordant seed is a fair coin flip, so this is the chance of a split at
+/// least as uneven as the one seen.
+pub fn sign_test_p_value(a_only: usize, b_only: usize) -> f64 {
+    let n = a_only + b_only;
+    if n == 0 {
+        return 1.0;
+    }
+    //sum the binomial(n, 1/2) tail up to the smaller count, in logs so
+    //large n doesn't underflow.
+    let mut ln_term = -(n as f64) * std::f64::consts::LN_2;
+    let mut tail = 0.0;
+    for k in 0..=a_only.min(b_only) {
+        tail += ln_term.exp();
+        ln_term += ((n - k) as f64).ln() - ((k + 1) as f64).ln();
+    }
+    (2.0 * tail).min(1.0)
+}
+
+/// Plays each seed under both variants, so both see identical deals and the
+/// results are paired seed by seed.
+pub fn compare(seeds: Range<u64>, a: &Rules, b: &Rules) -> Comparison {
+    let mut cmp = Comparison::default();
+    for seed in seeds {
+        let ra = play_game(Game::from_seed_with_rules(seed, a.clone()));
+        let rb = play_game(Game::from_seed_with_rules(seed, b.clone()));
+        let decided = !matches!(ra.result, Result::GaveUp | Result::Aborted)
+            && !matches!(rb.result, Result::GaveUp | Result::Aborted);
+        if decided && ra.result.is_winnable() != rb.result.is_winnable() {
+            if ra.result.is_winnable() {
+                cmp.a_only += 1;
+            } else {
+                cmp.b_only += 1;
+            }
+        }
+        cmp.a.add(&ra);
+        cmp.b.add(&rb);
+    }
+    cmp
+}
+
+/// A seed where the pruned search and the full search classify the game
+/// differently.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Disagreement {
+    pub seed: u64,
+    /// What `Search` found, skipping positions it had already expanded.
+    pub observed: SearchOutcome,
+    /// What `play_game_with_budget` found by walking every line.
+    pub proven: Result,
+}
+
+/// Both searches run over the same seeds.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CrossCheck {
+    pub games: usize,
+    /// Seeds where either search ran out of budget, so there's nothing to compare.
+    pub undecided: usize,
+    pub disagreements: Vec<Disagreement>,
+}
+
+/// Classifies every seed in `seeds` twice: once with the transposition-pruned
+/// `Search` and once with the exhaustive `play_game_with_budget`, recording
+/// every seed where they differ. Each search gets `budget` nodes or choice
+/// points respectively.
+pub fn cross_check(seeds: Range<u64>, rules: &Rules, budget: usize) -> CrossCheck {
+    let game = |seed| Game::from_seed_with_rules(seed, rules.clone());
+    cross_check_by(
+        seeds,
+        |seed| Search::new(game(seed)).step(budget),
+        |seed| play_game_with_budget(game(seed), budget).result,
+    )
+}
+//`cross_check` with the two searches passed in.
+fn cross_check_by(
+    seeds: Range<u64>,
+    observe: impl Fn(u64) -> Option<SearchOutcome>,
+    prove: impl Fn(u64) -> Result,
+) -> CrossCheck {
+    let mut check = CrossCheck::default();
+    for seed in seeds {
+        check.games += 1;
+        match (observe(seed), prove(seed)) {
+            (None, _) | (_, Result::GaveUp | Result::Aborted) => check.undecided += 1,
+            (Some(observed), proven) => {
+                if Result::from(observed) != proven {
+                    check.disagreements.push(Disagreement {
+                        seed,
+                        observed,
+                        proven,
+                    });
+                }
+            }
+        }
+    }
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a report with nothing in it but `result`, for handcrafted runs.
+    fn report(result: Result) -> GameReport {
+        GameReport {
+            seed: None,
+            choice_points: 0,
+            result,
+            final_pile_count: None,
+            near_miss: false,
+            branching_factor: 0.0,
+            max_tableau_len: 0,
+            winning_matches: MatchFrequency::default(),
+            shortest_win: None,
+            first_card: None,
+        }
+    }
+
+    #[test]
+    fn compare_counts_match_separate_sweeps() {
+        let a = Rules::default();
+        let b = Rules {
+            distances: [1, 2],
+            ..Rules::default()
+        };
+        let cmp = compare(0..4, &a, &b);
+        assert_eq!(cmp.a, sweep(0..4, &a));
+        assert_eq!(cmp.b, sweep(0..4, &b));
+        assert_eq!(cmp.a.games, 4);
+    }
+
+    #[test]
+    fn streak_counts_only_consecutive_forced_wins() {
+        use crate::Result::*;
+        let run = |results: &[Result]| {
+            let reports: Vec<_> = results.iter().map(|&r| report(r)).collect();
+            longest_forced_win_streak(&reports)
+        };
+        assert_eq!(run(&[]), 0);
+        assert_eq!(run(&[CanWin, GaveUp]), 0);
+        assert_eq!(
+            run(&[AlwaysWin, AlwaysWin, CanWin, AlwaysWin, AlwaysWin, AlwaysWin, AlwaysLose]),
+            3
+        );
+        //winnable isn't enough; a gave-up game breaks the streak too.
+        assert_eq!(run(&[AlwaysWin, GaveUp, AlwaysWin, CanWin, AlwaysWin]), 1);
+    }
+
+    #[test]
+    fn cross_check_reports_where_the_searches_differ() {
+        //the real searches agree wherever both finish, so stand in for a
+        //pruned search that missed seed 2's losing lines.
+        let observe = |seed| match seed {
+            2 => Some(SearchOutcome::AlwaysWin),
+            3 => None,
+            _ => Some(SearchOutcome::CanWin),
+        };
+        let check = cross_check_by(0..5, observe, |_| Result::CanWin);
+        assert_eq!(check.games, 5);
+        assert_eq!(check.undecided, 1);
+        assert_eq!(
+            check.disagreements,
+            [Disagreement {
+                seed: 2,
+                observed: SearchOutcome::AlwaysWin,
+                proven: Result::CanWin,
+            }]
+        );
+    }
+
+    #[test]
+    fn the_real_searches_agree_over_a_range_of_seeds() {
+        for rules in [
+            Rules::default(),
+            Rules {
+                distances: [1, 2],
+                ..Rules::default()
+            },
+        ] {
+            let check = cross_check(0..20, &rules, 20_000);
+            assert_eq!(check.games, 20);
+            //some seeds are too big to settle at this budget, but not most.
+            assert!(check.undecided < 10, "{} undecided", check.undecided);
+            assert_eq!(check.disagreements, []);
+        }
+    }
+
+    #[test]
+    fn win_rates_are_bucketed_by_widest_tableau() {
+        use crate::Result::*;
+        let mut stats = SweepStats::default();
+        for (max_tableau_len, result) in [
+            (4, CanWin),
+            (5, AlwaysWin),
+            (9, AlwaysLose),
+            (12, AlwaysLose),
+            (14, CanWin),
+        ] {
+            stats.add(&GameReport {
+                max_tableau_len,
+                ..report(result)
+            });
+        }
+        assert_eq!(
+            stats.win_rate_by_max_len(),
+            [(0, 1, 1.0), (5, 2, 0.5), (10, 2, 0.5)]
+        );
+    }
+
+    #[test]
+    fn sign_test_matches_exact_binomial_tails() {
+        let close = |a, b, p: f64| (sign_test_p_value(a, b) - p).abs() < 1e-12;
+        //2 * (1/2)^5
+        assert!(close(0, 5, 0.0625));
+        //2 * (1 + 10 + 45) / 1024
+        assert!(close(2, 8, 0.109_375));
+        assert!(close(8, 2, 0.109_375));
+        assert!(close(5, 5, 1.0));
+        assert!(close(0, 0, 1.0));
+        //an even split stays even however many seeds differ.
+        assert!(sign_test_p_value(1500, 1500) > 0.95);
+    }
+
+    #[test]
+    fn setting_the_stop_flag_ends_the_sweep_with_the_totals_so_far() {
+        let rules = Rules::default();
+        let stop = AtomicBool::new(false);
+        let mut played = 0;
+        let stats = sweep_until(0..50, &rules, 100, &stop, |_| {
+            played += 1;
+            if played == 3 {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        assert_eq!(stats, sweep_with(0..3, &rules, 100, |_| {}));
+
+        let stop = AtomicBool::new(false);
+        let stats = sweep_parallel(0..2000, &rules, 100, 4, &stop, |_| {
+            stop.store(true, Ordering::Relaxed)
+        });
+        //at most a reorder window of games gets started past the stop.
+        assert!(stats.games <= REORDER_WINDOW + 1);
+        assert_eq!(
+            stats,
+            sweep_with(0..stats.games as u64, &rules, 100, |_| {})
+        );
+    }
+
+    #[test]
+    fn a_panicking_game_ends_the_parallel_sweep_with_its_panic() {
+        let stop = AtomicBool::new(false);
+        let swept = panic::catch_unwind(|| {
+            sweep_parallel_by(
+                0..2000,
+                4,
+                &stop,
+                |seed| {
+                    assert_ne!(seed, 300, "seed 300 panics");
+                    report(Result::AlwaysWin)
+                },
+                |_| {},
+            )
+        });
+        let panic = swept.expect_err("the sweep should panic");
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("seed 300 panics"), "{}", message);
+    }
+
+    #[test]
+    fn win_lengths_bin_each_winnable_game_by_its_shortest_win() {
+        use crate::Result::*;
+        let mut stats = SweepStats::default();
+        for (result, shortest_win) in [
+            (AlwaysWin, Some(80)),
+            (CanWin, Some(80)),
+            (CanWin, Some(95)),
+            (AlwaysLose, None),
+            (GaveUp, None),
+        ] {
+            stats.add(&GameReport {
+                shortest_win,
+                ..report(result)
+            });
+        }
+        assert_eq!(stats.win_lengths, BTreeMap::from([(80, 2), (95, 1)]));
+    }
+
+    #[test]
+    fn first_card_buckets_go_by_its_rank_and_suit() {
+        use crate::Result::*;
+        let mut stats = SweepStats::default();
+        for (first, result) in [
+            ("AC", CanWin),
+            ("AH", AlwaysLose),
+            ("KC", AlwaysWin),
+            ("KS", CanWin),
+            ("7D", AlwaysLose),
+        ] {
+            stats.add(&GameReport {
+                first_card: Some(first.parse().unwrap()),
+                ..report(result)
+            });
+        }
+        let ranks = stats.win_rate_by_first_rank();
+        assert_eq!(ranks.len(), 13);
+        assert_eq!(ranks[0], (2, 0.5));
+        assert_eq!(ranks[6], (1, 0.0));
+        assert_eq!(ranks[12], (2, 1.0));
+        assert_eq!(ranks[1], (0, 0.0));
+        assert_eq!(
+            stats.win_rate_by_first_suit(),
+            [(2, 1.0), (1, 0.0), (1, 0.0), (1, 1.0)]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{Choices, Game, SearchOutcome, SophieError};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+//file header, followed by a little-endian u64 key and an outcome byte per entry.
+const MAGIC: &[u8; 4] = b"SGTT";
+
+/// Outcomes of positions already solved, keyed by a hash of the position,
+/// so a solve can skip whatever an earlier one settled. A table only holds
+/// for the rules it was filled under, and keys are 64-bit hashes, so two
+/// positions could in principle share one.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TranspositionTable {
+    entries: HashMap<u64, SearchOutcome>,
+}
+impl TranspositionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// The outcome stored for the position `game` is in, if it's been solved.
+    pub fn get(&self, game: &Game) -> Option<SearchOutcome> {
+        self.entries.get(&position_key(game)).copied()
+    }
+    pub fn insert(&mut self, game: &Game, outcome: SearchOutcome) {
+        self.entries.insert(position_key(game), outcome);
+    }
+    /// Writes the table to `path`, nine bytes an entry.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SophieError> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 9 * self.entries.len());
+        bytes.extend_from_slice(MAGIC);
+        for (key, outcome) in &self.entries {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.push(match outcome {
+                SearchOutcome::AlwaysWin => 0,
+                SearchOutcome::AlwaysLose => 1,
+                SearchOutcome::CanWin => 2,
+            });
+        }
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+    /// Reads a table written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SophieError> {
+        let bytes = fs::read(path)?;
+        let body = bytes
+            .strip_prefix(&MAGIC[..])
+            .filter(|b| b.len() % 9 == 0)
+            .ok_or_else(|| invalid("not a transposition table"))?;
+        let entries = body
+            .chunks_exact(9)
+            .map(|entry| {
+                let (key, outcome) = entry.split_at(8);
+                let outcome = match outcome[0] {
+                    0 => SearchOutcome::AlwaysWin,
+                    1 => SearchOutcome::AlwaysLose,
+                    2 => SearchOutcome::CanWin,
+                    _ => return Err(invalid("unknown outcome in transposition table")),
+                };
+                Ok((u64::from_le_bytes(key.try_into().unwrap()), outcome))
+            })
+            .collect::<Result<_, SophieError>>()?;
+        Ok(Self { entries })
+    }
+}
+
+fn invalid(message: &str) -> SophieError {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+//FNV-1a over `pack`, which is how `Search` tells positions apart.
+fn position_key(game: &Game) -> u64 {
+    game.pack().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl Game {
+    /// What every line of play from here comes to, as `Search` would find,
+    /// looking up and recording the outcome of each choice point in `table`.
+    /// A table carried between solves, or loaded from disk, lets later ones
+    /// skip positions earlier ones settled.
+    pub fn solve_with_table(&self, table: &mut TranspositionTable) -> SearchOutcome {
+        let mut g = self.clone_for_search();
+        let choices = match g.to_next_choice() {
+            Choices::GameWon => return SearchOutcome::AlwaysWin,
+            Choices::GameLost | Choices::Aborted => return SearchOutcome::AlwaysLose,
+            Choices::ChooseOne(c) => c,
+        };
+        if let Some(outcome) = table.get(&g) {
+            return outcome;
+        }
+        let outcome = if g.min_piles_lower_bound() > g.rules.winning_piles() {
+            SearchOutcome::AlwaysLose
+        } else {
+            let (mut wins, mut losses) = (false, false);
+            for m in choices {
+                let mut next = g.clone_for_search();
+                next.make_choice(m);
+                match next.solve_with_table(table) {
+                    SearchOutcome::AlwaysWin => wins = true,
+                    SearchOutcome::AlwaysLose => losses = true,
+                    SearchOutcome::CanWin => {
+                        wins = true;
+                        losses = true;
+                    }
+                }
+            }
+            match (wins, losses) {
+                (_, false) => SearchOutcome::AlwaysWin,
+                (false, true) => SearchOutcome::AlwaysLose,
+                (true, true) => SearchOutcome::CanWin,
+            }
+        };
+        table.insert(&g, outcome);
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_reloaded_table_answers_what_the_saved_one_did() {
+        let g = Game::from_seed(1392);
+        let mut table = TranspositionTable::new();
+        let outcome = g.solve_with_table(&mut table);
+        assert_eq!(outcome, SearchOutcome::CanWin);
+        assert!(!table.is_empty());
+
+        let path = std::env::temp_dir().join(format!("sophies-game-tt-{}.bin", std::process::id()));
+        table.save(&path).unwrap();
+        let loaded = TranspositionTable::load(&path);
+        fs::write(&path, b"SGTT\0").unwrap();
+        let truncated = TranspositionTable::load(&path);
+        fs::remove_file(&path).unwrap();
+        let mut loaded = loaded.unwrap();
+        assert_eq!(loaded, table);
+        assert!(truncated.is_err());
+
+        let mut first = g.clone();
+        first.to_next_choice();
+        assert_eq!(loaded.get(&first), Some(SearchOutcome::CanWin));
+        //a warm start answers from the table without adding to it.
+        assert_eq!(g.solve_with_table(&mut loaded), outcome);
+        assert_eq!(loaded.len(), table.len());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{Game, Match, PlacedCard, RemovalMode, Result};
+
+//a move made in place by `deal_undoable` or `match_undoable`, holding what
+//`unmake` needs to take it back without replaying the game.
+#[derive(Clone)]
+enum Unmake {
+    Deal,
+    //the pile that moved, and the one it landed on as it was before.
+    Merge {
+        m: Match,
+        moved: PlacedCard,
+        target: PlacedCard,
+    },
+    //both piles, as they were, under `RemovalMode::Discard`.
+    Discard {
+        m: Match,
+        from: PlacedCard,
+        to: PlacedCard,
+    },
+}
+
+impl Game {
+    //deals a card, leaving the move log alone.
+    fn deal_undoable(&mut self) -> Unmake {
+        self.deal_unlogged();
+        Unmake::Deal
+    }
+    //makes `m`, leaving the move log alone.
+    fn match_undoable(&mut self, m: Match) -> Unmake {
+        let to = m.0 - usize::from(m.1);
+        let undo = if self.rules.removal == RemovalMode::Discard {
+            Unmake::Discard {
+                m,
+                from: self.tableau[m.0].clone(),
+                to: self.tableau[to].clone(),
+            }
+        } else {
+            Unmake::Merge {
+                m,
+                moved: self.tableau[m.0].clone(),
+                target: self.tableau[to].clone(),
+            }
+        };
+        self.match_unlogged(m);
+        undo
+    }
+    //takes back a move from `deal_undoable` or `match_undoable`, the most
+    //recent one still standing.
+    fn unmake(&mut self, undo: Unmake) {
+        let max = self.rules.max_distance();
+        match undo {
+            Unmake::Deal => {
+                self.tableau.pop();
+                self.deck.pos -= 1;
+            }
+            Unmake::Merge { m, moved, target } => {
+                let to = m.0 - usize::from(m.1);
+                self.tableau[to] = target;
+                self.tableau.insert(m.0, moved);
+                //the same positions the match rechecked, as they were
+                //numbered before it.
+                for d in self.rules.distances {
+                    let ix = to + usize::from(d);
+                    if ix < m.0 {
+                        self.check_matches_at(ix);
+                    }
+                }
+                for ix in m.0 + 1..=m.0 + max {
+                    self.check_matches_at(ix);
+                }
+            }
+            Unmake::Discard {
+                m,
+                from,
+                to: target,
+            } => {
+                let to = m.0 - usize::from(m.1);
+                self.tableau.insert(to, target);
+                self.tableau.insert(m.0, from);
+                for ix in (to + 1..=to + max).chain(m.0 + 1..=m.0 + max) {
+                    self.check_matches_at(ix);
+                }
+            }
+        }
+    }
+
+    /// What `play_game_with_budget` finds for this game, walking the same
+    /// lines in the same order, but going back to each choice point by
+    /// taking moves back one at a time rather than restoring a copy of the
+    /// tableau. Only here to be measured against that, by
+    /// `benches/backtrack.rs`.
+    pub fn play_with_undo_log(&self, budget: usize) -> Result {
+        let mut g = self.clone_for_search();
+        if !g.check_on_deal {
+            g.recompute_all_matches();
+            g.check_on_deal = true;
+        }
+        let (mut wins, mut losses) = (0, 0);
+        let mut log = Vec::new();
+        //the log length at each choice point, and the choices there still to try.
+        let mut open: Vec<(usize, Vec<Match>)> = Vec::new();
+        loop {
+            loop {
+                if g.is_aborted() {
+                    return Result::Aborted;
+                }
+                if g.is_won() {
+                    wins += 1;
+                    break;
+                }
+                if g.is_lost() {
+                    losses += 1;
+                    break;
+                }
+                let choices = g.find_matches();
+                match choices.len() {
+                    0 => log.push(g.deal_undoable()),
+                    1 => log.push(g.match_undoable(choices[0])),
+                    _ => {
+                        g.choice_points += 1;
+                        open.push((log.len(), choices));
+                        break;
+                    }
+                }
+            }
+            if g.choice_points > budget {
+                return Result::GaveUp;
+            }
+            loop {
+                let (at, choices) = match open.last_mut() {
+                    Some(x) => x,
+                    None => {
+                        return match (wins, losses) {
+                            (_, 0) => Result::AlwaysWin,
+                            (0, _) => Result::AlwaysLose,
+                            _ => Result::CanWin,
+                        }
+                    }
+                };
+                while log.len() > *at {
+                    let undo = log.pop().unwrap();
+                    g.unmake(undo);
+                }
+                match choices.pop() {
+                    Some(m) => {
+                        log.push(g.match_undoable(m));
+                        break;
+                    }
+                    None => {
+                        open.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{play_game_with_budget, Rules};
+
+    #[test]
+    fn undo_log_search_agrees_with_the_snapshot_search() {
+        let variants = [
+            Rules::default(),
+            Rules {
+                removal: RemovalMode::Discard,
+                ..Rules::default()
+            },
+            Rules {
+                distances: [1, 2],
+                ..Rules::default()
+            },
+        ];
+        for rules in variants {
+            for seed in 0..30 {
+                let g = Game::from_seed_with_rules(seed, rules.clone());
+                let expected = play_game_with_budget(g.clone(), 300).result;
+                assert_eq!(g.play_with_undo_log(300), expected, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn unmaking_each_move_restores_the_position_and_its_flags() {
+        let state = |g: &Game| (g.pack(), g.enumerate().collect::<Vec<_>>());
+        for rules in [
+            Rules::default(),
+            Rules {
+                removal: RemovalMode::Discard,
+                ..Rules::default()
+            },
+        ] {
+            let mut g = Game::from_seed_with_rules(2, rules);
+            let mut log = Vec::new();
+            while g.can_continue() {
+                let before = state(&g);
+                let undo = match g.find_matches().last() {
+                    Some(&m) => g.match_undoable(m),
+                    None => g.deal_undoable(),
+                };
+                log.push((before, undo));
+            }
+            while let Some((before, undo)) = log.pop() {
+                g.unmake(undo);
+                assert_eq!(state(&g), before);
+            }
+            assert!(g.tableau.is_empty());
+        }
+    }
+}